@@ -0,0 +1,51 @@
+;; Exercises next: stepping over a trap, a call and a recursive call
+
+.CODE
+.ADDR x0000
+	CONST R6, #0
+	HICONST R6, x80		; R6 = x8000, the stack grows down from here
+	CONST R1, #0
+SKIP_TRAP
+	TRAP x01		; R1 = 1
+	CONST R0, #3
+SKIP_CALL
+	JSR COUNTDOWN
+AFTER_CALL
+	TRAP xFF		; HALT
+
+;; COUNTDOWN - decrements R0 down to 0, calling itself once per step
+.FALIGN
+COUNTDOWN
+	ADD R0, R0, #-1
+	BRz COUNTDOWN_DONE
+	ADD R6, R6, #-1
+	STR R7, R6, #0
+INNER_CALL
+	JSR COUNTDOWN
+AFTER_INNER_CALL
+	LDR R7, R6, #0
+	ADD R6, R6, #1
+COUNTDOWN_DONE
+	RET
+
+;=================================== OS ====================================;
+
+.OS
+.CODE
+
+.ADDR x8001
+	ADD R1, R1, #1
+	RTI
+
+.ADDR x80FF
+HALT
+	;; clear the run bit in the MCR (xFFEE), leaving R0 alone
+	CONST R1, xEE
+	HICONST R1, xFF
+	CONST R2, #0
+	STR R2, R1, #0
+
+.ADDR x8200
+.FALIGN
+	CONST R7, #0
+	RTI		; removes privilege bit
@@ -22,7 +22,11 @@ PASS:	nop
 
 .ADDR x80FF
 HALT
-	NOP
+	;; clear the run bit in the MCR (xFFEE), leaving R0 alone
+	CONST R1, xEE
+	HICONST R1, xFF
+	CONST R2, #0
+	STR R2, R1, #0
 
 .ADDR x8200
 .FALIGN
@@ -1,5 +1,5 @@
 ;;; This os will move the return value from main into r0 for testing purposes
-;;; It also moves the trap location to 0x80ff since the simulator (for the time being) is hardcoded to stop there
+;;; It halts with TRAP xFF, which simple_os.asm handles at x80FF by clearing the run bit in the MCR
 
 
 USER_STACK_ADDR .UCONST x7FFF
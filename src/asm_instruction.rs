@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::Span;
+
 #[derive(Clone, Copy, Debug)]
 pub struct InstructionWithLabel<'a> {
     pub ty: InstructionType,
@@ -8,6 +10,7 @@ pub struct InstructionWithLabel<'a> {
     pub rt: i8,
     pub immediate: i32,
     pub label: Option<&'a str>,
+    pub span: Option<Span<'a>>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -269,6 +272,7 @@ pub mod insn {
             rt,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -280,6 +284,7 @@ pub mod insn {
             rt,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -291,6 +296,7 @@ pub mod insn {
             rt,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -302,6 +308,7 @@ pub mod insn {
             rt,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -313,6 +320,7 @@ pub mod insn {
             rt,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -324,6 +332,7 @@ pub mod insn {
             rt: -1,
             immediate,
             label: None,
+            span: None,
         }
     }
 
@@ -335,6 +344,7 @@ pub mod insn {
             rt,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -346,6 +356,7 @@ pub mod insn {
             rt: -1,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -357,6 +368,7 @@ pub mod insn {
             rt,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -368,6 +380,7 @@ pub mod insn {
             rt,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -379,6 +392,7 @@ pub mod insn {
             rt: -1,
             immediate: offset,
             label: None,
+            span: None,
         }
     }
 
@@ -390,6 +404,7 @@ pub mod insn {
             rt: value,
             immediate: offset,
             label: None,
+            span: None,
         }
     }
 
@@ -401,6 +416,7 @@ pub mod insn {
             rt: -1,
             immediate: value,
             label: None,
+            span: None,
         }
     }
 
@@ -412,6 +428,7 @@ pub mod insn {
             rt: -1,
             immediate: value,
             label: None,
+            span: None,
         }
     }
 
@@ -423,6 +440,7 @@ pub mod insn {
             rt: -1,
             immediate: -1,
             label: Some(dest),
+            span: None,
         }
     }
 
@@ -434,6 +452,7 @@ pub mod insn {
             rt: -1,
             immediate: -1,
             label: None,
+            span: None,
         }
     }
 
@@ -445,6 +464,7 @@ pub mod insn {
             rt: -1,
             immediate: -1,
             label: Some(label),
+            span: None,
         }
     }
 }
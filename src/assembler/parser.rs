@@ -47,6 +47,8 @@ impl<'a, 'b> Parser<'a, 'b> {
             addr: None,
             labels: vec![],
             aligned: false,
+            file: 0,
+            variables: vec![],
             ty: if self.section == Section::Code {
                 BlockType::Code(vec![])
             } else {
@@ -239,6 +241,7 @@ impl<'a, 'b> Parser<'a, 'b> {
             rs: -1,
             immediate: i32::MAX,
             label: None,
+            span: None,
         };
         for (i, &spec) in specs.iter().enumerate() {
             // optional comma
@@ -380,6 +383,7 @@ impl<'a, 'b> Parser<'a, 'b> {
                 break;
             };
 
+            let span = i.span;
             self.consume();
 
             let ops = &mut [Operand::Label; 3];
@@ -387,10 +391,11 @@ impl<'a, 'b> Parser<'a, 'b> {
 
             let instruction = self.parse_instruction(instruction_type, ops);
 
-            let instruction = match instruction {
+            let mut instruction = match instruction {
                 Ok(i) => i,
                 Err(e) => return Err(e),
             };
+            instruction.span = Some(span);
 
             instructions.push(instruction);
         }
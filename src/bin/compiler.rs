@@ -1,6 +1,5 @@
 // @Todo error handling, use span information
 // @Todo indicate error in process return value
-// @Todo debug info: .loc and filename indices
 // @Todo name clashes and tests that fail
 // @Todo change Blocks in backend to not be an enum, but to seperate vecs
 
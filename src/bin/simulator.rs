@@ -1,7 +1,7 @@
 use clap::Parser;
 use std::path::PathBuf;
 
-use cereal::simulator::{run, Options};
+use cereal::simulator::{run, CaptureMode, Options, PipelineModel, TimerMode};
 
 #[derive(Parser)]
 struct Args {
@@ -16,10 +16,28 @@ struct Args {
     from_directory: Option<PathBuf>,
     #[clap(short)]
     script: Option<PathBuf>,
+    #[clap(long)]
+    wall_clock_timer: bool,
+    #[clap(long)]
+    capture_path: Option<PathBuf>,
+    #[clap(long)]
+    capture_each_frame: bool,
+    #[clap(long)]
+    load_use_penalty: Option<u64>,
+    #[clap(long)]
+    mispredict_penalty: Option<u64>,
 }
 
 fn main() {
     let args = Args::parse();
+    let mut pipeline = PipelineModel::default();
+    if let Some(penalty) = args.load_use_penalty {
+        pipeline.load_use_penalty = penalty;
+    }
+    if let Some(penalty) = args.mispredict_penalty {
+        pipeline.mispredict_penalty = penalty;
+    }
+
     let options = Options {
         input_paths: args.input_paths,
         trace_path: args.trace_path,
@@ -28,6 +46,10 @@ fn main() {
         headless: args.headless,
         from_directory: args.from_directory,
         startup_script: args.script,
+        timer_mode: if args.wall_clock_timer { TimerMode::WallClock } else { TimerMode::Steps },
+        capture_path: args.capture_path,
+        capture_mode: if args.capture_each_frame { CaptureMode::EachFrame } else { CaptureMode::OnHalt },
+        pipeline,
     };
     run(options);
 }
@@ -8,6 +8,24 @@ pub enum Data<'a> {
     Word(i16),
 }
 
+#[derive(Clone, Copy, Debug)]
+pub enum VariableType {
+    Int = 0,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum VariableLocation {
+    Global,     // at the start of the block
+    Frame(i16), // relative to the frame pointer of the procedure starting the block
+}
+
+#[derive(Debug)]
+pub struct Variable<'a> {
+    pub name: &'a str,
+    pub ty: VariableType,
+    pub location: VariableLocation,
+}
+
 #[derive(Debug)]
 pub enum BlockType<'a> {
     Code(Vec<InstructionWithLabel<'a>>),
@@ -19,6 +37,8 @@ pub struct Block<'a> {
     pub addr: Option<u16>,
     pub aligned: bool,
     pub labels: Vec<&'a str>,
+    pub file: usize, // index into the compiled input files, for debug info
+    pub variables: Vec<Variable<'a>>,
     pub ty: BlockType<'a>,
 }
 
@@ -1,4 +1,4 @@
-use crate::S;
+use crate::{Span, S};
 
 #[derive(Debug)]
 pub enum Type {
@@ -98,6 +98,7 @@ pub enum StatementType<'s> {
 #[derive(Debug)]
 pub struct Statement<'s> {
     pub ty: StatementType<'s>,
+    pub span: Span<'s>,
 }
 
 #[derive(Debug)]
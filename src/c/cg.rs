@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use crate::asm_instruction::{insn, InstructionWithLabel};
-use crate::block::{Block, BlockType, Data};
+use crate::block::{Block, BlockType, Data, Variable, VariableLocation, VariableType};
 use crate::c::ast::*;
 
 pub fn generate<'c, 's>(
@@ -57,6 +57,8 @@ impl<'c, 's> CgContext<'c, 's> {
                     addr: None,
                     aligned: false,
                     labels: Vec::new(),
+                    file: 0,
+                    variables: Vec::new(),
                     ty: BlockType::Code(Vec::new()),
                 };
 
@@ -76,6 +78,8 @@ impl<'c, 's> CgContext<'c, 's> {
             addr: None,
             aligned: false,
             labels: vec![label],
+            file: 0,
+            variables: Vec::new(),
             ty: BlockType::Data(Vec::new()),
         };
 
@@ -341,23 +345,38 @@ impl<'c, 's> CgContext<'c, 's> {
     }
 
     fn generate_statement(&mut self, statement: Statement<'s>) {
+        let first = self.instructions().len();
         match statement.ty {
             StatementType::Return(ret) => self.generate_return(ret),
             StatementType::Expression(expr) => self.generate_expression(expr, Location::Nowhere),
         }
+        for instruction in &mut self.instructions()[first..] {
+            instruction.span = Some(statement.span);
+        }
     }
 
     fn generate_procedure(&mut self, procedure: Procedure<'s>) {
         self.locals.clear();
+        let mut variables = Vec::new();
         for (dist, (_ty, name)) in procedure.params.iter().enumerate() {
-            self.locals
-                .insert(name, Location::OffsetFrame(dist as i32 + 3));
+            let offset = dist as i32 + 3;
+            self.locals.insert(name, Location::OffsetFrame(offset));
+            variables.push(Variable {
+                name,
+                ty: VariableType::Int,
+                location: VariableLocation::Frame(offset as i16),
+            });
         }
         let mut stack_index = -1;
         for decl in procedure.declarations {
             for (_, name) in decl.names {
                 self.locals
                     .insert(*name, Location::OffsetFrame(stack_index));
+                variables.push(Variable {
+                    name: *name,
+                    ty: VariableType::Int,
+                    location: VariableLocation::Frame(stack_index as i16),
+                });
                 stack_index -= 1;
             }
         }
@@ -374,11 +393,16 @@ impl<'c, 's> CgContext<'c, 's> {
         if stack_space != 0 {
             instructions.push(insn::addi(6, 6, stack_space));
         }
+        for instruction in &mut instructions {
+            instruction.span = Some(procedure.name.span);
+        }
 
         let block = Block {
             addr: None,
             aligned: true,
             labels: vec![*procedure.name],
+            file: 0,
+            variables,
             ty: BlockType::Code(instructions),
         };
         self.globals
@@ -396,6 +420,12 @@ impl<'c, 's> CgContext<'c, 's> {
             let data = self.new_data(*name);
             data.push(Data::Word(0));
             self.globals.insert(*name, Location::Label(*name));
+            let block = self.blocks.last_mut().unwrap();
+            block.variables.push(Variable {
+                name: *name,
+                ty: VariableType::Int,
+                location: VariableLocation::Global,
+            });
         }
     }
 
@@ -363,6 +363,7 @@ impl<'s> Parser<'s> {
         let ret = self
             .peek()
             .expect("Not the last token, expected to parse a statement");
+        let span = ret.span;
         let stmt_ty = match ret.ty {
             TokenType::Return => {
                 let ret = self.consume().unwrap();
@@ -376,7 +377,7 @@ impl<'s> Parser<'s> {
             }
         };
 
-        Ok(Statement { ty: stmt_ty })
+        Ok(Statement { ty: stmt_ty, span })
     }
 
     fn get_names(&mut self) -> Result<Vec<(usize, S<'s, &'s str>)>, Error> {
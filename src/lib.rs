@@ -23,6 +23,8 @@ const DATA_HEADER: u16 = 0xDADA;
 const SYMBOL_HEADER: u16 = 0xC3B7;
 const FILE_HEADER: u16 = 0xF17E;
 const LINE_HEADER: u16 = 0x715E;
+// Not part of PennSim's format, describes a C variable
+const VARIABLE_HEADER: u16 = 0x7A7E;
 
 pub fn number_fits(i: i32, signed: bool, bits: u8) -> bool {
     let mut min = 0;
@@ -58,6 +60,7 @@ pub fn compile(options: Options) -> Result<(), ()> {
     }
 
     for (i, path) in options.input_paths.iter().enumerate() {
+        let first_block = blocks.len();
         let extension = if let Some(e) = path.extension() {
             e
         } else {
@@ -82,9 +85,18 @@ pub fn compile(options: Options) -> Result<(), ()> {
             );
             return Err(());
         }
+
+        for block in &mut blocks[first_block..] {
+            block.file = i;
+        }
     }
 
-    let bytes = match link::link(&mut blocks, &constants, options.debug_info) {
+    let bytes = match link::link(
+        &mut blocks,
+        &constants,
+        &options.input_paths,
+        options.debug_info,
+    ) {
         Ok(bytes) => bytes,
         Err(()) => return Err(()),
     };
@@ -1,12 +1,17 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use crate::asm_instruction::{InstructionType, InstructionWithLabel};
-use crate::block::{Block, BlockType, Data};
-use crate::{number_fits, CODE_HEADER, DATA_HEADER, SYMBOL_HEADER};
+use crate::block::{Block, BlockType, Data, VariableLocation};
+use crate::{
+    number_fits, CODE_HEADER, DATA_HEADER, FILE_HEADER, LINE_HEADER, SYMBOL_HEADER,
+    VARIABLE_HEADER,
+};
 
 pub fn link(
     blocks: &mut [Block],
     constants: &HashMap<&str, i32>,
+    files: &[PathBuf],
     debug_info: bool,
 ) -> Result<Vec<u8>, ()> {
     // println!("PRINTED:");
@@ -22,7 +27,7 @@ pub fn link(
     // println!("EXPANDED:");
     // printer::print_blocks(blocks, constants).unwrap();
 
-    let labels = match patch(blocks) {
+    let mut labels = match patch(blocks) {
         Ok(labels) => labels,
         Err(errors) => {
             for error in errors {
@@ -35,7 +40,10 @@ pub fn link(
     // println!("PATCHED:");
     // printer::print_blocks(blocks, constants).unwrap();
 
-    let bytes = write_object_code(&*blocks, &labels, debug_info);
+    // Constants are parsed as labels of whatever block follows them, but they aren't addresses
+    labels.retain(|label, _| !constants.contains_key(label));
+
+    let bytes = write_object_code(&*blocks, &labels, files, debug_info);
     Ok(bytes)
 }
 
@@ -235,7 +243,12 @@ fn patch<'a>(blocks: &mut [Block<'a>]) -> Result<HashMap<&'a str, u16>, Vec<Stri
     }
 }
 
-fn write_object_code(blocks: &[Block], labels: &HashMap<&str, u16>, debug_info: bool) -> Vec<u8> {
+fn write_object_code(
+    blocks: &[Block],
+    labels: &HashMap<&str, u16>,
+    files: &[PathBuf],
+    debug_info: bool,
+) -> Vec<u8> {
     fn write_be(bytes: &mut Vec<u8>, short: u16) {
         bytes.push(((short & 0xff00) >> 8) as u8);
         bytes.push((short & 0xff) as u8);
@@ -255,6 +268,43 @@ fn write_object_code(blocks: &[Block], labels: &HashMap<&str, u16>, debug_info:
             write_be(&mut bytes, label.len() as u16);
             bytes.extend_from_slice(label.as_bytes());
         }
+
+        for file in files {
+            let name = file.to_string_lossy();
+            write_be(&mut bytes, FILE_HEADER);
+            write_be(&mut bytes, name.len() as u16);
+            bytes.extend_from_slice(name.as_bytes());
+        }
+
+        for block in blocks {
+            let BlockType::Code(instructions) = &block.ty else { continue };
+            let address = block.addr.unwrap();
+            for (i, instruction) in instructions.iter().enumerate() {
+                let Some(span) = instruction.span else { continue };
+                write_be(&mut bytes, LINE_HEADER);
+                write_be(&mut bytes, address + i as u16);
+                write_be(&mut bytes, span.line as u16);
+                write_be(&mut bytes, block.file as u16);
+            }
+        }
+
+        // kind (0 global, 1 frame), procedure, address or frame offset, type, name
+        for block in blocks {
+            let address = block.addr.unwrap();
+            for variable in &block.variables {
+                let (kind, procedure, value) = match variable.location {
+                    VariableLocation::Global => (0, 0, address),
+                    VariableLocation::Frame(offset) => (1, address, offset as u16),
+                };
+                write_be(&mut bytes, VARIABLE_HEADER);
+                write_be(&mut bytes, kind);
+                write_be(&mut bytes, procedure);
+                write_be(&mut bytes, value);
+                write_be(&mut bytes, variable.ty as u16);
+                write_be(&mut bytes, variable.name.len() as u16);
+                bytes.extend_from_slice(variable.name.as_bytes());
+            }
+        }
     }
 
     for block in blocks {
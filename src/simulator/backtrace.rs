@@ -0,0 +1,187 @@
+use std::fmt::Write;
+
+use super::decode::decode;
+use super::machine::{Machine, Variable, VariableScope};
+use super::InstructionType;
+
+/// Instructions `c::cg` emits before the frame pointer is valid: save the return address,
+/// save the frame pointer, move the stack pointer past them and point R5 at the new frame
+const PROLOGUE_LENGTH: u16 = 4;
+
+/// A stack frame laid out by the C compiler. Relative to R5 the caller's frame pointer is at 0,
+/// the return address at 1, the return value at 2, arguments start at 3 and locals grow down
+/// from -1.
+pub(super) struct Frame {
+    pub pc: u16,
+    /// Address of the first instruction of the procedure
+    pub procedure: u16,
+    pub frame_pointer: u16,
+    /// False while the prologue or return is executing, when R5 is still the caller's and the
+    /// locals aren't allocated
+    pub set_up: bool,
+    pub arguments: Vec<u16>,
+    pub locals: Vec<u16>,
+}
+
+fn is_instruction(machine: &Machine, address: u16, ty: InstructionType, rd: u8, rs: u8, immediate: i16) -> bool {
+    decode(machine.memory[address as usize], &mut None).is_ok_and(|i| {
+        // Stores keep the register being stored in rt
+        let destination = if i.ty == InstructionType::Str { i.rt } else { i.rd };
+        i.ty == ty && destination == rd && i.rs == rs && i.immediate == immediate
+    })
+}
+
+/// The start of the C procedure containing `pc`, recognised by the prologue `c::cg` emits
+fn procedure_start(machine: &Machine, pc: u16) -> Option<u16> {
+    let (_, start) = machine.nearest_symbol(pc)?;
+    let saves_return_address = is_instruction(machine, start, InstructionType::Str, 7, 6, -2);
+    let saves_frame_pointer = is_instruction(machine, start.wrapping_add(1), InstructionType::Str, 5, 6, -3);
+    (saves_return_address && saves_frame_pointer).then_some(start)
+}
+
+/// Locals are allocated by the optional fifth prologue instruction, `ADD R6, R6, #-n`
+fn local_count(machine: &Machine, start: u16) -> u16 {
+    let word = machine.memory[start.wrapping_add(PROLOGUE_LENGTH) as usize];
+    match decode(word, &mut None) {
+        Ok(i) if i.ty == InstructionType::Addi && i.rd == 6 && i.rs == 6 && i.immediate < 0 => {
+            i.immediate.unsigned_abs()
+        }
+        _ => 0,
+    }
+}
+
+/// Parameters are only known from the procedure's variable debug info. The slots between a
+/// frame's arguments and its caller's locals can also hold the caller's pending arguments for
+/// an outer call or spilled registers, so they can't be counted from the stack.
+fn parameter_count(machine: &Machine, start: u16) -> u16 {
+    let parameters = machine.variables.iter().filter(|v| {
+        matches!(v.scope, VariableScope::Local { procedure, offset } if procedure == start && offset >= 3)
+    });
+    parameters.count() as u16
+}
+
+/// Walks the frame pointer chain from the PC outwards, stopping at the first caller that
+/// is not a C procedure
+pub(super) fn backtrace(machine: &Machine) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut pc = machine.pc;
+    let mut frame_pointer = machine.registers[5] as u16;
+
+    let Some(start) = procedure_start(machine, pc) else { return frames };
+    let returning = is_instruction(machine, pc, InstructionType::Jmpr, 0, 7, 0);
+    if pc - start < PROLOGUE_LENGTH || returning {
+        // The frame pointer will be, or was, 3 below the stack pointer the caller left. The
+        // third prologue instruction has already moved the stack pointer there.
+        let stack_pointer = machine.registers[6] as u16;
+        let callee_frame_pointer = if pc - start == PROLOGUE_LENGTH - 1 {
+            stack_pointer
+        } else {
+            stack_pointer.wrapping_sub(3)
+        };
+        frames.push(Frame {
+            pc,
+            procedure: start,
+            frame_pointer: callee_frame_pointer,
+            set_up: false,
+            arguments: (0..parameter_count(machine, start))
+                .map(|i| callee_frame_pointer.wrapping_add(3 + i))
+                .collect(),
+            locals: Vec::new(),
+        });
+        pc = machine.registers[7] as u16;
+    }
+
+    while let Some(start) = procedure_start(machine, pc) {
+        let locals = (1..=local_count(machine, start))
+            .map(|i| frame_pointer.wrapping_sub(i))
+            .collect();
+
+        let caller_frame_pointer = machine.memory[frame_pointer as usize];
+        let return_address = machine.memory[frame_pointer.wrapping_add(1) as usize];
+        // Callers' frames are always further up the stack
+        let caller = procedure_start(machine, return_address).filter(|_| caller_frame_pointer > frame_pointer);
+
+        let arguments = (0..parameter_count(machine, start))
+            .map(|i| frame_pointer.wrapping_add(3 + i))
+            .collect();
+
+        frames.push(Frame {
+            pc,
+            procedure: start,
+            frame_pointer,
+            set_up: true,
+            arguments,
+            locals,
+        });
+
+        if caller.is_none() {
+            break;
+        }
+        pc = return_address;
+        frame_pointer = caller_frame_pointer;
+    }
+
+    frames
+}
+
+/// The frame's header line followed by one line per argument and local
+pub(super) fn describe(machine: &Machine, index: usize, frame: &Frame) -> String {
+    let mut output = String::new();
+
+    let function = match machine.nearest_symbol(frame.pc) {
+        Some((label, addr)) if addr == frame.pc => label.to_string(),
+        Some((label, addr)) => format!("{label}+{}", frame.pc - addr),
+        None => "??".to_string(),
+    };
+    let _ = write!(output, "#{} x{:04X} {}", index, frame.pc, function);
+    if let Some(location) = machine.source_location(frame.pc) {
+        let _ = write!(output, " at {}", machine.describe_location(location));
+    }
+    output.push('\n');
+
+    if !frame.set_up {
+        output.push_str("    (frame not set up, locals not allocated)\n");
+    }
+    let frame_pointer = frame.frame_pointer;
+    let slots = frame.arguments.iter().map(|a| ("argument", a)).chain(frame.locals.iter().map(|l| ("local", l)));
+    for (kind, &address) in slots {
+        let offset = address.wrapping_sub(frame_pointer) as i16;
+        let value = machine.memory[address as usize];
+        let _ = write!(output, "    {:<8} R5{:+} x{:04X} = x{:04X} ({})", kind, offset, address, value, value as i16);
+        let variable = machine.variables.iter().find(|v| {
+            v.scope == VariableScope::Local { procedure: frame.procedure, offset }
+        });
+        if let Some(variable) = variable {
+            let _ = write!(output, " {} {}", variable.ty, variable.name);
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// The arguments and locals of the innermost frame followed by the globals, with their
+/// addresses. Only arguments are visible while the innermost frame is being set up or torn down.
+pub(super) fn visible_variables(machine: &Machine) -> Vec<(&Variable, u16)> {
+    let frames = backtrace(machine);
+    let innermost = frames.first();
+
+    let locals = machine.variables.iter().filter_map(|v| match (v.scope, innermost) {
+        (VariableScope::Local { procedure, offset }, Some(frame))
+            if procedure == frame.procedure && (frame.set_up || offset >= 3) =>
+        {
+            Some((v, frame.frame_pointer.wrapping_add(offset as u16)))
+        }
+        _ => None,
+    });
+    let globals = machine.variables.iter().filter_map(|v| match v.scope {
+        VariableScope::Global { address } => Some((v, address)),
+        _ => None,
+    });
+    locals.chain(globals).collect()
+}
+
+/// `type name xADDR = xVALUE (decimal)`
+pub(super) fn describe_variable(machine: &Machine, variable: &Variable, address: u16) -> String {
+    let value = machine.memory[address as usize];
+    format!("{} {} x{:04X} = x{:04X} ({})", variable.ty, variable.name, address, value, value as i16)
+}
@@ -0,0 +1,159 @@
+use super::InstructionType;
+
+#[derive(Copy, Clone, Default)]
+struct Entry {
+    valid: bool,
+    tag: u16,
+    target: u16,
+    /// Two bit saturating counter, predicting taken when at least 2
+    counter: u8,
+}
+
+/// A branch target buffer with a two bit counter per entry, indexed by the low bits of the PC
+pub struct BranchPredictor {
+    entries: Vec<Entry>,
+    pub predictions: u64,
+    pub hits: u64,
+    pub mispredicts: u64,
+}
+
+impl BranchPredictor {
+    /// `size` must be a power of two so the table can be indexed by PC bits
+    pub fn new(size: usize) -> Option<Self> {
+        if !size.is_power_of_two() || size > 1 << 16 {
+            return None;
+        }
+        Some(BranchPredictor {
+            entries: vec![Entry::default(); size],
+            predictions: 0,
+            hits: 0,
+            mispredicts: 0,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Predicts the instruction after the one at `pc`, then trains on where it actually went.
+    /// Returns whether the prediction was wrong.
+    pub(super) fn resolve(&mut self, pc: u16, next_pc: u16) -> bool {
+        let index = pc as usize & (self.entries.len() - 1);
+        let entry = &mut self.entries[index];
+        let hit = entry.valid && entry.tag == pc;
+        let fall_through = pc.wrapping_add(1);
+
+        let predicted = if hit && entry.counter >= 2 {
+            entry.target
+        } else {
+            fall_through
+        };
+
+        let taken = next_pc != fall_through;
+        if taken {
+            if hit {
+                entry.counter = (entry.counter + 1).min(3);
+            } else {
+                entry.valid = true;
+                entry.tag = pc;
+                entry.counter = 2;
+            }
+            entry.target = next_pc;
+        } else if hit {
+            entry.counter = entry.counter.saturating_sub(1);
+        }
+
+        let mispredicted = predicted != next_pc;
+        self.predictions += 1;
+        if hit {
+            self.hits += 1;
+        }
+        if mispredicted {
+            self.mispredicts += 1;
+        }
+        mispredicted
+    }
+}
+
+/// The control transfers the predictor is consulted for
+pub(super) fn is_predicted(ty: InstructionType) -> bool {
+    use InstructionType::*;
+    matches!(ty, Brp | Brz | Brzp | Brn | Brnp | Brnz | Brnzp | Jmp | Jsr | Jmpr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unseen_taken_branch_is_allocated() {
+        let mut predictor = BranchPredictor::new(16).unwrap();
+        assert!(predictor.resolve(0x0010, 0x0040));
+        assert_eq!((predictor.predictions, predictor.hits, predictor.mispredicts), (1, 0, 1));
+
+        let entry = predictor.entries[0];
+        assert!(entry.valid);
+        assert_eq!((entry.tag, entry.target, entry.counter), (0x0010, 0x0040, 2));
+    }
+
+    #[test]
+    fn not_taken_branch_is_not_allocated() {
+        let mut predictor = BranchPredictor::new(16).unwrap();
+        assert!(!predictor.resolve(0x0010, 0x0011));
+        assert!(!predictor.entries[0].valid);
+    }
+
+    #[test]
+    fn loop_branch_predicted_taken_after_one_taken() {
+        let mut predictor = BranchPredictor::new(16).unwrap();
+        assert!(predictor.resolve(0x0010, 0x0008));
+        assert!(!predictor.resolve(0x0010, 0x0008));
+        assert!(!predictor.resolve(0x0010, 0x0008));
+        // Falling out of the loop
+        assert!(predictor.resolve(0x0010, 0x0011));
+        assert_eq!((predictor.predictions, predictor.hits, predictor.mispredicts), (4, 3, 2));
+    }
+
+    #[test]
+    fn tag_alias_replaces_entry() {
+        let mut predictor = BranchPredictor::new(4).unwrap();
+        predictor.resolve(0x0010, 0x0008);
+        // x0014 has the same index but a different tag, so it misses and takes the entry over
+        assert!(predictor.resolve(0x0014, 0x0030));
+        assert_eq!(predictor.hits, 0);
+        assert_eq!((predictor.entries[0].tag, predictor.entries[0].target), (0x0014, 0x0030));
+        assert!(predictor.resolve(0x0010, 0x0008));
+        assert_eq!(predictor.hits, 0);
+    }
+
+    #[test]
+    fn counter_saturates() {
+        let mut predictor = BranchPredictor::new(16).unwrap();
+        for _ in 0..5 {
+            predictor.resolve(0x0010, 0x0008);
+        }
+        assert_eq!(predictor.entries[0].counter, 3);
+        // One not taken outcome from 3 still predicts taken
+        assert!(predictor.resolve(0x0010, 0x0011));
+        assert!(!predictor.resolve(0x0010, 0x0008));
+
+        for _ in 0..5 {
+            predictor.resolve(0x0010, 0x0011);
+        }
+        assert_eq!(predictor.entries[0].counter, 0);
+        assert!(predictor.entries[0].valid);
+        // It takes two taken outcomes to predict taken again
+        assert!(predictor.resolve(0x0010, 0x0008));
+        assert_eq!(predictor.entries[0].counter, 1);
+        assert!(predictor.resolve(0x0010, 0x0008));
+        assert!(!predictor.resolve(0x0010, 0x0008));
+    }
+
+    #[test]
+    fn size_must_be_power_of_two() {
+        assert!(BranchPredictor::new(12).is_none());
+        assert!(BranchPredictor::new(0).is_none());
+        assert_eq!(BranchPredictor::new(1 << 16).unwrap().size(), 1 << 16);
+        assert!(BranchPredictor::new(1 << 17).is_none());
+    }
+}
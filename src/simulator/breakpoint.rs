@@ -0,0 +1,337 @@
+use std::fmt;
+
+use super::command::{parse_register, parse_value};
+use super::machine::{self, Machine, MemoryAccess};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Operand {
+    Register(usize),
+    Pc,
+    Psr,
+    Flag(u16),
+    Memory(u16),
+    Value(u16),
+}
+
+impl Operand {
+    fn parse(machine: &Machine, text: &str) -> Option<Operand> {
+        if let Some(address) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            return parse_value(machine, address.trim()).map(Operand::Memory);
+        }
+        if let Some(r) = parse_register(text) {
+            return Some(Operand::Register(r));
+        }
+        match &*text.to_lowercase() {
+            "pc" => Some(Operand::Pc),
+            "psr" => Some(Operand::Psr),
+            "n" => Some(Operand::Flag(machine::N)),
+            "z" => Some(Operand::Flag(machine::Z)),
+            "p" => Some(Operand::Flag(machine::P)),
+            _ => parse_value(machine, text).map(Operand::Value),
+        }
+    }
+
+    fn evaluate(self, machine: &Machine) -> i16 {
+        match self {
+            Operand::Register(r) => machine.registers[r],
+            Operand::Pc => machine.pc as i16,
+            Operand::Psr => machine.psr as i16,
+            Operand::Flag(mask) => (machine.psr & mask > 0) as i16,
+            Operand::Memory(address) => machine.memory[address as usize] as i16,
+            Operand::Value(value) => value as i16,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Register(r) => write!(f, "R{r}"),
+            Operand::Pc => write!(f, "PC"),
+            Operand::Psr => write!(f, "PSR"),
+            Operand::Flag(machine::N) => write!(f, "N"),
+            Operand::Flag(machine::Z) => write!(f, "Z"),
+            Operand::Flag(_) => write!(f, "P"),
+            Operand::Memory(address) => write!(f, "[x{address:04X}]"),
+            Operand::Value(value) => write!(f, "x{value:04X}"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    LessOrEqual,
+    GreaterOrEqual,
+    Less,
+    Greater,
+}
+
+/// Two character operators come first so '<' doesn't split '<='
+const OPERATORS: [(&str, Operator); 6] = [
+    ("==", Operator::Equal),
+    ("!=", Operator::NotEqual),
+    ("<=", Operator::LessOrEqual),
+    (">=", Operator::GreaterOrEqual),
+    ("<", Operator::Less),
+    (">", Operator::Greater),
+];
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (symbol, _) = OPERATORS.iter().find(|(_, op)| op == self).expect("Every operator has a symbol");
+        write!(f, "{symbol}")
+    }
+}
+
+/// A comparison between two operands, compared as signed values. A lone operand is true when it
+/// is nonzero.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) struct Condition {
+    lhs: Operand,
+    operator: Option<Operator>,
+    rhs: Operand,
+}
+
+impl Condition {
+    pub(super) fn parse(machine: &Machine, text: &str) -> Result<Condition, String> {
+        let operand = |text: &str| {
+            let text = text.trim();
+            Operand::parse(machine, text).ok_or_else(|| format!("Invalid operand ('{}')", text))
+        };
+
+        let split = OPERATORS
+            .iter()
+            .find_map(|&(symbol, op)| text.split_once(symbol).map(|(lhs, rhs)| (op, lhs, rhs)));
+        match split {
+            Some((operator, lhs, rhs)) => Ok(Condition {
+                lhs: operand(lhs)?,
+                operator: Some(operator),
+                rhs: operand(rhs)?,
+            }),
+            None => Ok(Condition {
+                lhs: operand(text)?,
+                operator: None,
+                rhs: Operand::Value(0),
+            }),
+        }
+    }
+
+    fn evaluate(&self, machine: &Machine) -> bool {
+        let lhs = self.lhs.evaluate(machine);
+        let rhs = self.rhs.evaluate(machine);
+        match self.operator {
+            Some(Operator::Equal) => lhs == rhs,
+            Some(Operator::LessOrEqual) => lhs <= rhs,
+            Some(Operator::GreaterOrEqual) => lhs >= rhs,
+            Some(Operator::Less) => lhs < rhs,
+            Some(Operator::Greater) => lhs > rhs,
+            Some(Operator::NotEqual) | None => lhs != rhs,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.operator {
+            Some(operator) => write!(f, "{} {} {}", self.lhs, operator, self.rhs),
+            None => write!(f, "{}", self.lhs),
+        }
+    }
+}
+
+pub(super) struct Breakpoint {
+    pub label: String,
+    pub condition: Option<Condition>,
+    /// How many times the breakpoint is passed over before it stops execution
+    pub ignore_count: u32,
+    pub hits: u32,
+}
+
+impl Breakpoint {
+    pub(super) fn new(label: String) -> Self {
+        Breakpoint {
+            label,
+            condition: None,
+            ignore_count: 0,
+            hits: 0,
+        }
+    }
+
+    /// Called when the PC reaches the breakpoint, returns whether execution should stop
+    pub(super) fn hit(&mut self, machine: &Machine) -> bool {
+        if !self.condition.is_none_or(|c| c.evaluate(machine)) {
+            return false;
+        }
+        self.hits += 1;
+        self.hits > self.ignore_count
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({})", self.label)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " if {condition}")?;
+        }
+        if self.ignore_count > 0 {
+            write!(f, " hits {}/{}", self.hits, self.ignore_count + 1)?;
+        } else {
+            write!(f, " hits {}", self.hits)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+/// Stops execution after an instruction touches any of `len` words starting at `start`
+pub(super) struct Watchpoint {
+    pub kind: WatchKind,
+    pub start: u16,
+    pub len: u16,
+    pub label: String,
+}
+
+impl Watchpoint {
+    pub(super) fn matches(&self, access: &MemoryAccess) -> bool {
+        let kind_matches = match self.kind {
+            WatchKind::Read => access.is_read,
+            WatchKind::Write => !access.is_read,
+            WatchKind::Access => true,
+        };
+        let offset = access.address.wrapping_sub(self.start);
+        kind_matches && offset < self.len
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access",
+        };
+        write!(f, "{} x{:04X}", kind, self.start)?;
+        if self.len > 1 {
+            write!(f, "-x{:04X}", self.start.wrapping_add(self.len - 1))?;
+        }
+        write!(f, " ({})", self.label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_condition() {
+        let mut machine = Machine::new();
+        machine.symbols.insert("COUNT".to_string(), 0x4000);
+
+        let condition = Condition::parse(&machine, "R1 <= x10").unwrap();
+        assert_eq!(condition.lhs, Operand::Register(1));
+        assert_eq!(condition.operator, Some(Operator::LessOrEqual));
+        assert_eq!(condition.rhs, Operand::Value(0x10));
+
+        let condition = Condition::parse(&machine, "[COUNT] != #-1").unwrap();
+        assert_eq!(condition.lhs, Operand::Memory(0x4000));
+        assert_eq!(condition.operator, Some(Operator::NotEqual));
+        assert_eq!(condition.to_string(), "[x4000] != xFFFF");
+
+        let condition = Condition::parse(&machine, "z").unwrap();
+        assert_eq!(condition.lhs, Operand::Flag(machine::Z));
+        assert_eq!(condition.operator, None);
+        assert_eq!(condition.to_string(), "Z");
+
+        assert!(Condition::parse(&machine, "R9 == 1").is_err());
+        assert!(Condition::parse(&machine, "R1 < ").is_err());
+    }
+
+    #[test]
+    fn evaluate_condition() {
+        let mut machine = Machine::new();
+        machine.registers[1] = -2;
+        machine.memory[0x4000] = 5;
+
+        let holds = |text| Condition::parse(&machine, text).unwrap().evaluate(&machine);
+        // Compared as signed values
+        assert!(holds("R1 < 0"));
+        assert!(holds("R1 >= #-2"));
+        assert!(holds("[x4000] == 5"));
+        assert!(!holds("[x4000] > 5"));
+        assert!(holds("R1"));
+        assert!(!holds("R2"));
+    }
+
+    #[test]
+    fn ignore_count() {
+        let machine = Machine::new();
+        let mut breakpoint = Breakpoint::new("LOOP".to_string());
+        breakpoint.ignore_count = 2;
+        assert!(!breakpoint.hit(&machine));
+        assert!(!breakpoint.hit(&machine));
+        assert!(breakpoint.hit(&machine));
+        assert!(breakpoint.hit(&machine));
+        assert_eq!(breakpoint.hits, 4);
+    }
+
+    fn access(address: u16, is_read: bool) -> MemoryAccess {
+        MemoryAccess { address, is_read, old_value: 0, new_value: 0 }
+    }
+
+    fn watchpoint(kind: WatchKind, start: u16, len: u16) -> Watchpoint {
+        Watchpoint { kind, start, len, label: String::new() }
+    }
+
+    #[test]
+    fn watchpoint_kind() {
+        let read = watchpoint(WatchKind::Read, 0x4000, 1);
+        assert!(read.matches(&access(0x4000, true)));
+        assert!(!read.matches(&access(0x4000, false)));
+
+        let write = watchpoint(WatchKind::Write, 0x4000, 1);
+        assert!(!write.matches(&access(0x4000, true)));
+        assert!(write.matches(&access(0x4000, false)));
+
+        let any = watchpoint(WatchKind::Access, 0x4000, 1);
+        assert!(any.matches(&access(0x4000, true)));
+        assert!(any.matches(&access(0x4000, false)));
+    }
+
+    #[test]
+    fn watchpoint_range() {
+        let watchpoint = watchpoint(WatchKind::Access, 0x4000, 3);
+        assert!(!watchpoint.matches(&access(0x3FFF, true)));
+        assert!(watchpoint.matches(&access(0x4000, true)));
+        assert!(watchpoint.matches(&access(0x4002, true)));
+        assert!(!watchpoint.matches(&access(0x4003, true)));
+        assert_eq!(watchpoint.to_string(), "access x4000-x4002 ()");
+    }
+
+    #[test]
+    fn watchpoint_range_wraps() {
+        let watchpoint = watchpoint(WatchKind::Write, 0xFFFF, 2);
+        assert!(watchpoint.matches(&access(0xFFFF, false)));
+        assert!(watchpoint.matches(&access(0x0000, false)));
+        assert!(!watchpoint.matches(&access(0x0001, false)));
+        assert!(!watchpoint.matches(&access(0xFFFE, false)));
+        assert_eq!(watchpoint.to_string(), "write xFFFF-x0000 ()");
+    }
+
+    #[test]
+    fn false_condition_does_not_count_as_hit() {
+        let machine = Machine::new();
+        let mut breakpoint = Breakpoint::new("LOOP".to_string());
+        breakpoint.condition = Some(Condition::parse(&machine, "R0 == 1").unwrap());
+        breakpoint.ignore_count = 1;
+        assert!(!breakpoint.hit(&machine));
+        assert_eq!(breakpoint.hits, 0);
+    }
+}
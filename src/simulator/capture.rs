@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::machine::{VIDEO_HEIGHT, VIDEO_WIDTH};
+
+/// When a headless run writes the display out to an image
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// Once, after the machine halts
+    #[default]
+    OnHalt,
+    /// Every time a frame is presented through the VDCR, numbering the files
+    EachFrame,
+}
+
+/// Splits a 16 bit RGB555 pixel into 8 bit channels
+pub(super) fn unpack_rgb555(pixel: u16) -> [u8; 3] {
+    fn unpack(b: u16, s: u8) -> u8 {
+        ((b >> s) as u8 & ((1 << 5) - 1)) << 3
+    }
+    [unpack(pixel, 10), unpack(pixel, 5), unpack(pixel, 0)]
+}
+
+/// `frame.png` becomes `frame_0001.png` for frame 1
+pub(super) fn numbered_path(path: &Path, frame: u64) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{stem}_{frame:04}");
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/// Writes a frame of the display as a PPM if the path ends in `.ppm`, and as a PNG otherwise
+pub(super) fn write_frame(path: &Path, pixels: &[u16]) -> io::Result<()> {
+    let rgb = pixels.iter().flat_map(|&p| unpack_rgb555(p)).collect::<Vec<_>>();
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("ppm")) {
+        write!(writer, "P6\n{} {}\n255\n", VIDEO_WIDTH, VIDEO_HEIGHT)?;
+        writer.write_all(&rgb)?;
+        return writer.flush();
+    }
+
+    let mut encoder = png::Encoder::new(writer, VIDEO_WIDTH as u32, VIDEO_HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::from)?;
+    writer.write_image_data(&rgb).map_err(io::Error::from)
+}
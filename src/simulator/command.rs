@@ -1,33 +1,41 @@
 use once_cell::sync::Lazy;
 use std::collections::BTreeMap;
-use crate::simulator::{loader, CerealApp, ExecutionState, Machine};
+use crate::simulator::backtrace;
+use crate::simulator::bpred::BranchPredictor;
+use crate::simulator::breakpoint::{Breakpoint, Condition, WatchKind, Watchpoint};
+use crate::simulator::source_step::{self, ReturnPoint, SourceStep};
+use crate::simulator::{capture, decode, disassemble, loader, machine, CerealApp, ExecutionState, Machine};
 
 static HELP_MESSAGES: Lazy<BTreeMap<&str, &str>> = Lazy::new(|| {
     let mut map = BTreeMap::new();
     map.insert("as", "as usage: as <outfilename> <infilename>+");
-    map.insert("b", "b[reak] usage: b[reak] [ set | clear ] [ mem_addr | label ]");
-    map.insert("bpred", "bpred usage: bpred <size>");
+    map.insert("b", "b[reak] usage: b[reak] [ set | clear ] [ mem_addr | label ] [ if condition ] [ ignore count ]");
+    map.insert("backtrace", "backtrace usage: backtrace");
+    map.insert("bpred", "bpred usage: bpred [<size>]");
     map.insert("c", "c[ontinue] usage: c[ontinue]");
+    map.insert("capture", "capture usage: capture <filename>");
     map.insert("check", "check usage: check [ count | cumulative | reset | PC | reg | PSR | MPR | mem_addr | label | N | Z | P ] [ mem_addr | label ] [ value | label ]");
     map.insert("clear", "clear usage: clear");
-    map.insert("counters", "counters usage: counters");
+    map.insert("counters", "counters usage: counters [reset]");
     map.insert("d", "d[ump] usage: d[ump] [-check | -coe | -readmemh | -disasm] from_mem_addr to_mem_addr dumpfile");
+    map.insert("frames", "frames usage: frames");
     map.insert("goto", "goto usage: goto [<addr>|<label>]");
     map.insert("h", "h[elp] usage: h[elp] [command]");
     map.insert("input", "input usage: input <filename>");
     map.insert("l", "l[ist] usage: l[ist] [ addr1 | label1 [addr2 | label2] ]");
     map.insert("ld", "l[oa]d usage: l[oa]d <filename>");
     map.insert("loadhex", "loadhex usage: loadhex hexfile");
-    map.insert("next", "n[ext] usage: n[ext]");
-    map.insert("p", "p[rint] usage: p[rint]");
+    map.insert("next", "n[ext] usage: n[ext] [-source]");
+    map.insert("p", "p[rint] usage: p[rint] [var <name>]");
     map.insert("pwd", "pwd usage: pwd");
     map.insert("quit", "quit usage: quit");
     map.insert("reset", "reset usage: reset");
-    map.insert("s", "s[tep] usage: s[tep]"); // abbreviations for correct sorting
+    map.insert("s", "s[tep] usage: s[tep] [-source]"); // abbreviations for correct sorting
     map.insert("script", "script usage: script <filename>");
     map.insert("set", "set usage: set [ PC | reg | PSR | MPR | mem_addr | label ] [ mem_addr | label ] [ value | N | Z | P ]");
     map.insert("stop", "stop usage: stop");
     map.insert("trace", "trace usage: trace [on <trace-file> | off]");
+    map.insert("watch", "watch usage: watch [ read | write | access ] [ mem_addr | label ] [ len ] | watch clear [ mem_addr | label ]");
     map
 });
 
@@ -76,16 +84,60 @@ pub(crate) fn command(app: &mut CerealApp, frame: Option<&mut eframe::Frame>, cm
                 return;
             };
 
-            let Some(&addr) = app.machine.symbols.get(label) else {
-                app.command_output.push_str(&format!("Error: Invalid label ('{}')\n", label));
+            let Some(addr) = parse_value(&app.machine, label) else {
+                app.command_output.push_str(&format!("Error: Invalid address or label ('{}')\n", label));
                 return;
             };
 
+            let options = words.collect::<Vec<_>>();
+            let ignore_at = options.iter().position(|w| w.eq_ignore_ascii_case("ignore"));
+            let (condition_words, ignore_words) = options.split_at(ignore_at.unwrap_or(options.len()));
+
+            let condition = match condition_words {
+                [] => None,
+                [first, rest @ ..] if first.eq_ignore_ascii_case("if") && !rest.is_empty() => {
+                    match Condition::parse(&app.machine, &rest.join(" ")) {
+                        Ok(condition) => Some(condition),
+                        Err(e) => {
+                            app.command_output.push_str(&format!("Error: {e}\n"));
+                            return;
+                        }
+                    }
+                }
+                _ => {
+                    app.command_output.push_str(HELP_MESSAGES["b"]);
+                    app.command_output.push('\n');
+                    return;
+                }
+            };
+            let ignore_count = match ignore_words {
+                [] => 0,
+                [_, count] => match parse_value(&app.machine, count) {
+                    Some(count) => count as u32,
+                    None => {
+                        app.command_output.push_str(&format!("Error: Invalid ignore count ('{}')\n", count));
+                        return;
+                    }
+                },
+                _ => {
+                    app.command_output.push_str(HELP_MESSAGES["b"]);
+                    app.command_output.push('\n');
+                    return;
+                }
+            };
+
             let verb = match kind {
+                Kind::Set if condition.is_some() || ignore_count > 0 => {
+                    let mut breakpoint = Breakpoint::new(label.to_string());
+                    breakpoint.condition = condition;
+                    breakpoint.ignore_count = ignore_count;
+                    app.breakpoints.insert(addr, breakpoint);
+                    "set"
+                }
                 Kind::Set => {
                     use std::collections::btree_map::Entry;
                     if let Entry::Vacant(e) = app.breakpoints.entry(addr) {
-                        e.insert(label.to_string());
+                        e.insert(Breakpoint::new(label.to_string()));
                         "set"
                     } else {
                         app.breakpoints.remove(&addr);
@@ -99,18 +151,97 @@ pub(crate) fn command(app: &mut CerealApp, frame: Option<&mut eframe::Frame>, cm
             };
             app.command_output.push_str(&format!("Breakpoint {verb} at x{addr:04X}\n"));
         },
-        "bpred" => app.command_output.push_str("Unimplemented\n"),
+        "backtrace" | "bt" => {
+            let output = call_stack(&app.machine);
+            app.command_output.push_str(&output);
+        }
+        "bpred" => {
+            let Some(size) = words.next() else {
+                let output = branch_predictor(&app.machine);
+                app.command_output.push_str(&output);
+                return;
+            };
+            let Some(predictor) = parse_value(&app.machine, size).and_then(|s| BranchPredictor::new(s as usize)) else {
+                app.command_output.push_str(&format!("Error: Branch predictor size must be a power of two ('{}')\n", size));
+                return;
+            };
+            app.command_output.push_str(&format!("Branch predictor set to {} entries\n", predictor.size()));
+            app.machine.branch_predictor = Some(predictor);
+        }
         "c" | "continue" => {
+            app.next_stop = None;
+            app.source_step = None;
             app.execution_state = ExecutionState::Running;
             app.command_output.push_str("use the 'stop' command to interrupt execution\n");
         },
-        "check" => app.command_output.push_str("Unimplemented\n"),
+        "capture" => {
+            let Some(filename) = words.next() else {
+                app.command_output.push_str(HELP_MESSAGES["capture"]);
+                app.command_output.push('\n');
+                return;
+            };
+            match capture::write_frame(filename.as_ref(), app.machine.frame()) {
+                Ok(()) => app.command_output.push_str(&format!("Display captured to {filename}\n")),
+                Err(e) => app.command_output.push_str(&format!("Unable to write to file '{filename}': {e}\n")),
+            }
+        }
+        "check" => check(app, words),
         "clear" => app.command_output.clear(),
-        "counters" => app.command_output.push_str("Unimplemented\n"),
-        "d" | "dump" => app.command_output.push_str("Unimplemented\n"),
-        "goto" => app.command_output.push_str("Unimplemented\n"),
-        "input" => app.command_output.push_str("Unimplemented\n"),
-        "l" | "list" => app.command_output.push_str("Unimplemented\n"),
+        "counters" => match words.next().map(str::to_lowercase).as_deref() {
+            None => {
+                let output = counters(&app.machine);
+                app.command_output.push_str(&output);
+            }
+            Some("reset") => {
+                app.machine.counters = Default::default();
+                app.command_output.push_str("Counters reset\n");
+            }
+            Some(_) => {
+                app.command_output.push_str(HELP_MESSAGES["counters"]);
+                app.command_output.push('\n');
+            }
+        },
+        "d" | "dump" => {
+            let output = dump(&app.machine, words);
+            app.command_output.push_str(&output);
+        }
+        "frames" => {
+            app.command_output.push_str(&format!("{} frames presented\n", app.machine.devices.frames));
+        }
+        "goto" => {
+            let Some(target) = words.next() else {
+                app.command_output.push_str(HELP_MESSAGES["goto"]);
+                app.command_output.push('\n');
+                return;
+            };
+            let Some(addr) = parse_value(&app.machine, target) else {
+                app.command_output.push_str(&format!("Error: Invalid address or label ('{}')\n", target));
+                return;
+            };
+            if !machine::is_executable(addr) {
+                app.command_output.push_str(&format!("Error: x{addr:04X} is not in a code section\n"));
+                return;
+            }
+            app.machine.pc = addr;
+            app.command_output.push_str(&format!("PC set to x{addr:04X}\n"));
+        }
+        "input" => {
+            let Some(filename) = words.next() else {
+                app.command_output.push_str(HELP_MESSAGES["input"]);
+                app.command_output.push('\n');
+                return;
+            };
+            let Ok(bytes) = std::fs::read(filename) else {
+                app.command_output.push_str(&format!("{} (No such file or directory)\n", filename));
+                return;
+            };
+            app.machine.devices.keyboard.extend(&bytes);
+            app.command_output.push_str(&format!("Queued {} bytes of keyboard input from {}\n", bytes.len(), filename));
+        }
+        "l" | "list" => {
+            let output = list(&app.machine, words);
+            app.command_output.push_str(&output);
+        }
         "ld" | "load" => {
             if let Some(filename) = words.next() {
                 let output = load_from_filename(filename, &mut app.machine);
@@ -121,9 +252,54 @@ pub(crate) fn command(app: &mut CerealApp, frame: Option<&mut eframe::Frame>, cm
                 app.command_output.push('\n');
             }
         }
-        "loadhex" => app.command_output.push_str("Unimplemented\n"),
-        "n" | "next" => app.command_output.push_str("Unimplemented\n"),
-        "p" | "print" => app.command_output.push_str("Unimplemented\n"),
+        "loadhex" => {
+            if let Some(filename) = words.next() {
+                let output = load_hex(filename, &mut app.machine);
+                app.command_output.push_str(&output);
+                app.command_output.push('\n');
+            } else {
+                app.command_output.push_str(HELP_MESSAGES["loadhex"]);
+                app.command_output.push('\n');
+            }
+        }
+        "n" | "next" => {
+            match words.next() {
+                None => {}
+                Some("-source") => {
+                    source_step(app, true);
+                    return;
+                }
+                Some(_) => {
+                    app.command_output.push_str(HELP_MESSAGES["next"]);
+                    app.command_output.push('\n');
+                    return;
+                }
+            }
+
+            let pc = app.machine.pc;
+            if !source_step::is_call(&app.machine, pc) {
+                step(app);
+                return;
+            }
+
+            // Calls leave their return address in R7, so run until we get back to it
+            app.next_stop = Some(ReturnPoint::new(&app.machine));
+            app.execution_state = ExecutionState::Running;
+        },
+        "p" | "print" => match (words.next(), words.next()) {
+            (None, _) => {
+                let output = print(&app.machine);
+                app.command_output.push_str(&output);
+            }
+            (Some("var"), Some(name)) => {
+                let output = print_variable(&app.machine, name);
+                app.command_output.push_str(&output);
+            }
+            _ => {
+                app.command_output.push_str(HELP_MESSAGES["p"]);
+                app.command_output.push('\n');
+            }
+        },
         "pwd" => {
             let pwd = std::env::current_dir().expect("Current directory is valid");
             app.command_output.push_str(&pwd.to_string_lossy());
@@ -131,6 +307,10 @@ pub(crate) fn command(app: &mut CerealApp, frame: Option<&mut eframe::Frame>, cm
         },
         "quit" => {
             if let Some(frame) = frame {
+                // Scripts run unattended, so leave what they printed where a grader can read it
+                if app.scripted {
+                    print!("{}", app.command_output);
+                }
                 frame.close();
             } else {
                 app.command_output.push_str("Quitting on app creation is unsupported\n");
@@ -139,9 +319,20 @@ pub(crate) fn command(app: &mut CerealApp, frame: Option<&mut eframe::Frame>, cm
         "reset" => {
             app.machine.reset();
             app.breakpoints.clear();
+            app.watchpoints.clear();
+            app.next_stop = None;
+            app.source_step = None;
+            app.console_output.clear();
             app.command_output.push_str("System reset\n");
         },
-        "s" | "step" => app.command_output.push_str("Unimplemented\n"),
+        "s" | "step" => match words.next() {
+            None => step(app),
+            Some("-source") => source_step(app, false),
+            Some(_) => {
+                app.command_output.push_str(HELP_MESSAGES["s"]);
+                app.command_output.push('\n');
+            }
+        },
         "script" => {
             let Some(filename) = words.next() else {
                 app.command_output.push_str(HELP_MESSAGES["script"]);
@@ -164,8 +355,10 @@ pub(crate) fn command(app: &mut CerealApp, frame: Option<&mut eframe::Frame>, cm
                 }
             }
         },
-        "set" => app.command_output.push_str("Unimplemented\n"),
+        "set" => set(app, words),
         "stop" => {
+            app.next_stop = None;
+            app.source_step = None;
             app.execution_state = ExecutionState::Suspended;
             app.command_output.push_str(&format!("Stopped at x{:04X}\n", app.machine.pc));
         },
@@ -195,6 +388,7 @@ pub(crate) fn command(app: &mut CerealApp, frame: Option<&mut eframe::Frame>, cm
                 },
             }
         },
+        "watch" => watch(app, words),
         unknown => {
             app.command_output.push_str("Unknown command: ");
             app.command_output.push_str(unknown);
@@ -203,6 +397,547 @@ pub(crate) fn command(app: &mut CerealApp, frame: Option<&mut eframe::Frame>, cm
     }
 }
 
+#[derive(Default)]
+pub(super) struct CheckCounts {
+    passed: u32,
+    failed: u32,
+}
+
+/// Parses a number in any of the forms the assembler accepts (`x1234`, `0x1234`, `#12`, `#-12`
+/// or `12`), falling back to looking it up as a label.
+pub(super) fn parse_value(machine: &Machine, word: &str) -> Option<u16> {
+    let hex = word
+        .strip_prefix("0x")
+        .or_else(|| word.strip_prefix("0X"))
+        .or_else(|| word.strip_prefix('x'))
+        .or_else(|| word.strip_prefix('X'));
+    if let Some(hex) = hex {
+        if let Ok(value) = u16::from_str_radix(hex, 16) {
+            return Some(value);
+        }
+    }
+
+    let decimal = word.strip_prefix('#').unwrap_or(word);
+    if let Ok(value) = decimal.parse::<u16>() {
+        return Some(value);
+    }
+    if let Ok(value) = decimal.parse::<i16>() {
+        return Some(value as u16);
+    }
+
+    machine.symbols.get(word).copied()
+}
+
+pub(super) fn parse_register(word: &str) -> Option<usize> {
+    let index = word.strip_prefix('r').or_else(|| word.strip_prefix('R'))?;
+    match index.parse::<usize>() {
+        Ok(i) if i < 8 => Some(i),
+        _ => None,
+    }
+}
+
+fn check<'a>(app: &mut CerealApp, mut words: impl Iterator<Item = &'a str>) {
+    let Some(target) = words.next() else {
+        app.command_output.push_str(HELP_MESSAGES["check"]);
+        app.command_output.push('\n');
+        return;
+    };
+    let args = words.collect::<Vec<_>>();
+
+    // Each check produces the name of what was checked, its actual value and the expected value
+    let result = match &*target.to_lowercase() {
+        "count" => {
+            let CheckCounts { passed, failed } = app.checks;
+            app.command_output.push_str(&format!("{passed} checks passed, {failed} checks failed\n"));
+            return;
+        }
+        "cumulative" => {
+            let CheckCounts { passed, failed } = app.cumulative_checks;
+            app.command_output.push_str(&format!("Cumulative: {passed} checks passed, {failed} checks failed\n"));
+            return;
+        }
+        "reset" => {
+            app.checks = CheckCounts::default();
+            app.command_output.push_str("Check counts reset\n");
+            return;
+        }
+        "mpr" => {
+            app.command_output.push_str("Error: LC4 has no MPR\n");
+            return;
+        }
+        bit @ ("n" | "z" | "p") => {
+            let (name, mask) = match bit {
+                "n" => ("N", machine::N),
+                "z" => ("Z", machine::Z),
+                _ => ("P", machine::P),
+            };
+            let passed = app.machine.psr & mask > 0;
+            record_check(app, passed);
+            let verb = if passed { "passed" } else { "failed" };
+            app.command_output.push_str(&format!("Check {verb}: {name} is {}\n", if passed { "set" } else { "not set" }));
+            return;
+        }
+        "pc" => args.first().map(|&v| (vec![("PC".to_string(), app.machine.pc)], v)),
+        "psr" => args.first().map(|&v| (vec![("PSR".to_string(), app.machine.psr)], v)),
+        other => {
+            if let Some(r) = parse_register(other) {
+                args.first().map(|&v| (vec![(format!("R{r}"), app.machine.registers[r] as u16)], v))
+            } else {
+                let Some(start) = parse_value(&app.machine, target) else {
+                    app.command_output.push_str(&format!("Error: Invalid address or label ('{}')\n", target));
+                    return;
+                };
+                let (end, value) = match args[..] {
+                    [value] => (Some(start), value),
+                    [end, value] => (parse_value(&app.machine, end), value),
+                    _ => (None, ""),
+                };
+                let Some(end) = end.filter(|&end| end >= start) else {
+                    app.command_output.push_str(HELP_MESSAGES["check"]);
+                    app.command_output.push('\n');
+                    return;
+                };
+                let cells = (start..=end)
+                    .map(|addr| (format!("x{addr:04X}"), app.machine.memory[addr as usize]))
+                    .collect::<Vec<_>>();
+                Some((cells, value))
+            }
+        }
+    };
+
+    let Some((actuals, expected)) = result else {
+        app.command_output.push_str(HELP_MESSAGES["check"]);
+        app.command_output.push('\n');
+        return;
+    };
+    let Some(expected) = parse_value(&app.machine, expected) else {
+        app.command_output.push_str(&format!("Error: Invalid value ('{}')\n", expected));
+        return;
+    };
+
+    for (name, actual) in actuals {
+        let passed = actual == expected;
+        record_check(app, passed);
+        if passed {
+            app.command_output.push_str(&format!("Check passed: {name} is x{actual:04X}\n"));
+        } else {
+            app.command_output.push_str(&format!("Check failed: {name} is x{actual:04X}, expected x{expected:04X}\n"));
+        }
+    }
+}
+
+fn set<'a>(app: &mut CerealApp, mut words: impl Iterator<Item = &'a str>) {
+    let Some(target) = words.next() else {
+        app.command_output.push_str(HELP_MESSAGES["set"]);
+        app.command_output.push('\n');
+        return;
+    };
+    let args = words.collect::<Vec<_>>();
+
+    let parse_arg = |app: &mut CerealApp, word: Option<&&str>| {
+        let Some(&word) = word else {
+            app.command_output.push_str(HELP_MESSAGES["set"]);
+            app.command_output.push('\n');
+            return None;
+        };
+        let value = parse_value(&app.machine, word);
+        if value.is_none() {
+            app.command_output.push_str(&format!("Error: Invalid value ('{}')\n", word));
+        }
+        value
+    };
+
+    let condition_code = |word: &str| match &*word.to_lowercase() {
+        "n" => Some(machine::N),
+        "z" => Some(machine::Z),
+        "p" => Some(machine::P),
+        _ => None,
+    };
+    let set_condition_code = |app: &mut CerealApp, mask: u16| {
+        app.machine.psr = (app.machine.psr & !(machine::N | machine::Z | machine::P)) | mask;
+        app.command_output.push_str(&format!("PSR set to x{:04X}\n", app.machine.psr));
+    };
+
+    match &*target.to_lowercase() {
+        "mpr" => app.command_output.push_str("Error: LC4 has no MPR\n"),
+        "n" | "z" | "p" => set_condition_code(app, condition_code(target).unwrap()),
+        "pc" => {
+            let Some(value) = parse_arg(app, args.first()) else { return };
+            app.machine.pc = value;
+            app.command_output.push_str(&format!("PC set to x{value:04X}\n"));
+        }
+        "psr" => {
+            if let Some(mask) = args.first().and_then(|w| condition_code(w)) {
+                set_condition_code(app, mask);
+                return;
+            }
+            let Some(value) = parse_arg(app, args.first()) else { return };
+            app.machine.psr = value;
+            app.command_output.push_str(&format!("PSR set to x{value:04X}\n"));
+        }
+        other => {
+            if let Some(r) = parse_register(other) {
+                let Some(value) = parse_arg(app, args.first()) else { return };
+                app.machine.registers[r] = value as i16;
+                app.command_output.push_str(&format!("R{r} set to x{value:04X}\n"));
+                return;
+            }
+
+            let Some(start) = parse_value(&app.machine, target) else {
+                app.command_output.push_str(&format!("Error: Invalid address or label ('{}')\n", target));
+                return;
+            };
+            let (end, value) = match args[..] {
+                [_] => (Some(start), args.first()),
+                [end, _] => (parse_value(&app.machine, end), args.get(1)),
+                _ => (None, None),
+            };
+            let Some(end) = end.filter(|&end| end >= start) else {
+                app.command_output.push_str(HELP_MESSAGES["set"]);
+                app.command_output.push('\n');
+                return;
+            };
+            let Some(value) = parse_arg(app, value) else { return };
+            for cell in &mut app.machine.memory[start as usize..=end as usize] {
+                *cell = value;
+            }
+            if start == end {
+                app.command_output.push_str(&format!("Memory x{start:04X} set to x{value:04X}\n"));
+            } else {
+                app.command_output.push_str(&format!("Memory x{start:04X} to x{end:04X} set to x{value:04X}\n"));
+            }
+        }
+    }
+}
+
+fn watch<'a>(app: &mut CerealApp, mut words: impl Iterator<Item = &'a str>) {
+    let kind = match words.next().map(str::to_lowercase).as_deref() {
+        Some("read") => WatchKind::Read,
+        Some("write") => WatchKind::Write,
+        Some("access") => WatchKind::Access,
+        Some("clear") => {
+            match words.next() {
+                None => {
+                    app.watchpoints.clear();
+                    app.command_output.push_str("Watchpoints cleared\n");
+                }
+                Some(target) => {
+                    let Some(addr) = parse_value(&app.machine, target) else {
+                        app.command_output.push_str(&format!("Error: Invalid address or label ('{}')\n", target));
+                        return;
+                    };
+                    let count = app.watchpoints.len();
+                    app.watchpoints.retain(|w| w.start != addr);
+                    if app.watchpoints.len() == count {
+                        app.command_output.push_str(&format!("Error: No watchpoint at x{addr:04X}\n"));
+                    } else {
+                        app.command_output.push_str(&format!("Watchpoint cleared at x{addr:04X}\n"));
+                    }
+                }
+            }
+            return;
+        }
+        _ => {
+            app.command_output.push_str(HELP_MESSAGES["watch"]);
+            app.command_output.push('\n');
+            return;
+        }
+    };
+
+    let Some(target) = words.next() else {
+        app.command_output.push_str(HELP_MESSAGES["watch"]);
+        app.command_output.push('\n');
+        return;
+    };
+    let Some(start) = parse_value(&app.machine, target) else {
+        app.command_output.push_str(&format!("Error: Invalid address or label ('{}')\n", target));
+        return;
+    };
+    let len = match words.next() {
+        None => 1,
+        Some(len) => match parse_value(&app.machine, len) {
+            Some(len) if len > 0 => len,
+            _ => {
+                app.command_output.push_str(&format!("Error: Invalid length ('{}')\n", len));
+                return;
+            }
+        },
+    };
+
+    let watchpoint = Watchpoint {
+        kind,
+        start,
+        len,
+        label: target.to_string(),
+    };
+    app.command_output.push_str(&format!("Watchpoint set on {watchpoint}\n"));
+    app.watchpoints.push(watchpoint);
+}
+
+fn record_check(app: &mut CerealApp, passed: bool) {
+    for counts in [&mut app.checks, &mut app.cumulative_checks] {
+        if passed {
+            counts.passed += 1;
+        } else {
+            counts.failed += 1;
+        }
+    }
+}
+
+fn dump<'a>(machine: &Machine, words: impl Iterator<Item = &'a str>) -> String {
+    use std::fmt::Write;
+
+    enum Format { Plain, Check, Coe, Readmemh, Disasm }
+
+    let mut words = words.peekable();
+    let format = match words.peek().map(|w| w.to_lowercase()).as_deref() {
+        Some("-check") => Format::Check,
+        Some("-coe") => Format::Coe,
+        Some("-readmemh") => Format::Readmemh,
+        Some("-disasm") => Format::Disasm,
+        Some(flag) if flag.starts_with('-') => return format!("{}\n", HELP_MESSAGES["d"]),
+        _ => Format::Plain,
+    };
+    if !matches!(format, Format::Plain) {
+        words.next();
+    }
+
+    let (Some(from), Some(to), Some(filename)) = (words.next(), words.next(), words.next()) else {
+        return format!("{}\n", HELP_MESSAGES["d"]);
+    };
+    let Some(from) = parse_value(machine, from) else {
+        return format!("Error: Invalid address or label ('{}')\n", from);
+    };
+    let Some(to) = parse_value(machine, to) else {
+        return format!("Error: Invalid address or label ('{}')\n", to);
+    };
+    if to < from {
+        return format!("Error: x{to:04X} comes before x{from:04X}\n");
+    }
+
+    let range = from..=to;
+    let mut output = String::new();
+    match format {
+        Format::Plain => {
+            for addr in range {
+                let _ = writeln!(output, "{:04X}: {:04X}", addr, machine.memory[addr as usize]);
+            }
+        }
+        Format::Check => {
+            for addr in range {
+                let _ = writeln!(output, "check x{:04X} x{:04X}", addr, machine.memory[addr as usize]);
+            }
+        }
+        Format::Coe => {
+            output.push_str("memory_initialization_radix=16;\n");
+            output.push_str("memory_initialization_vector=\n");
+            for addr in range {
+                let terminator = if addr == to { ';' } else { ',' };
+                let _ = writeln!(output, "{:04X}{}", machine.memory[addr as usize], terminator);
+            }
+        }
+        Format::Readmemh => {
+            let _ = writeln!(output, "@{:04X}", from);
+            for addr in range {
+                let _ = writeln!(output, "{:04X}", machine.memory[addr as usize]);
+            }
+        }
+        Format::Disasm => {
+            for addr in range {
+                let word = machine.memory[addr as usize];
+                match decode::decode(word, &mut None) {
+                    Ok(instruction) => { let _ = writeln!(output, "x{:04X} {:04X} {}", addr, word, instruction); }
+                    Err(_) => { let _ = writeln!(output, "x{:04X} {:04X} ;; INVALID INSTRUCTION", addr, word); }
+                }
+            }
+        }
+    }
+
+    match std::fs::write(filename, output) {
+        Ok(()) => format!("Memory dumped to {filename}\n"),
+        Err(e) => format!("Unable to write to file '{filename}': {e}\n"),
+    }
+}
+
+fn list<'a>(machine: &Machine, mut words: impl Iterator<Item = &'a str>) -> String {
+    use std::fmt::Write;
+
+    const LIST_LENGTH: u16 = 20;
+
+    let range = match (words.next(), words.next()) {
+        (None, _) => {
+            let start = machine.pc.saturating_sub(LIST_LENGTH / 4);
+            start..=start.saturating_add(LIST_LENGTH - 1)
+        }
+        (Some(from), to) => {
+            let Some(start) = parse_value(machine, from) else {
+                return format!("Error: Invalid address or label ('{}')\n", from);
+            };
+            let end = match to {
+                Some(to) => match parse_value(machine, to) {
+                    Some(end) => end,
+                    None => return format!("Error: Invalid address or label ('{}')\n", to),
+                },
+                None => start.saturating_add(LIST_LENGTH - 1),
+            };
+            if end < start {
+                return format!("Error: x{end:04X} comes before x{start:04X}\n");
+            }
+            start..=end
+        }
+    };
+
+    let mut output = String::new();
+    let mut last_location = None;
+    for addr in range {
+        // Interleave the source line whenever we move on to a new one
+        if let Some(location) = machine.source_location(addr) {
+            if last_location != Some(location) {
+                let text = machine.source_line(location).unwrap_or("").trim();
+                let _ = writeln!(output, "; {}: {}", machine.describe_location(location), text);
+                last_location = Some(location);
+            }
+        }
+
+        let marker = if addr == machine.pc { "-->" } else { "   " };
+        let labels = machine.labels_at(addr).join(", ");
+        let _ = writeln!(
+            output,
+            "{} x{:04X} {:04X} {:<16} {}",
+            marker,
+            addr,
+            machine.memory[addr as usize],
+            labels,
+            disassemble(machine, addr)
+        );
+    }
+    output
+}
+
+fn counters(machine: &Machine) -> String {
+    use std::fmt::Write;
+
+    let counters = &machine.counters;
+    let model = &machine.pipeline;
+    let cycles = counters.cycles(model);
+    let cpi = if counters.instructions > 0 { cycles as f64 / counters.instructions as f64 } else { 0.0 };
+
+    let mut output = String::new();
+    let _ = writeln!(output, "Instructions:       {}", counters.instructions);
+    let _ = writeln!(output, "Loads:              {}", counters.loads);
+    let _ = writeln!(output, "Stores:             {}", counters.stores);
+    let _ = writeln!(output, "Branches taken:     {}", counters.branches_taken);
+    let _ = writeln!(output, "Branches not taken: {}", counters.branches_not_taken);
+    let _ = writeln!(output, "Jumps:              {}", counters.jumps);
+    let _ = writeln!(output, "Traps:              {}", counters.traps);
+    let _ = writeln!(output, "Load-use stalls:    {} ({} cycles each)", counters.load_use_stalls, model.load_use_penalty);
+    let _ = writeln!(output, "Mispredicts:        {} ({} cycles each)", counters.mispredicts, model.mispredict_penalty);
+    let _ = writeln!(
+        output,
+        "TRAP/RTI/JSRR:      {} ({} cycles each, never predicted)",
+        counters.unpredicted_redirects, model.mispredict_penalty
+    );
+    let _ = writeln!(output, "Cycles:             {} (CPI {:.3})", cycles, cpi);
+    output
+}
+
+fn call_stack(machine: &Machine) -> String {
+    let frames = backtrace::backtrace(machine);
+    if frames.is_empty() {
+        return "No stack frames, the PC is not in a C procedure\n".to_string();
+    }
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| backtrace::describe(machine, i, frame))
+        .collect()
+}
+
+fn branch_predictor(machine: &Machine) -> String {
+    use std::fmt::Write;
+
+    let Some(predictor) = &machine.branch_predictor else {
+        return "No branch predictor, always predicting not taken\n".to_string();
+    };
+    let accuracy = if predictor.predictions > 0 {
+        100.0 * (predictor.predictions - predictor.mispredicts) as f64 / predictor.predictions as f64
+    } else {
+        0.0
+    };
+
+    let mut output = String::new();
+    let _ = writeln!(output, "Branch predictor:   {} entries", predictor.size());
+    let _ = writeln!(output, "Predictions:        {}", predictor.predictions);
+    let _ = writeln!(output, "BTB hits:           {}", predictor.hits);
+    let _ = writeln!(output, "Mispredicts:        {} ({:.1}% accurate)", predictor.mispredicts, accuracy);
+    output
+}
+
+fn print(machine: &Machine) -> String {
+    use std::fmt::Write;
+
+    let mut output = String::new();
+    for (row, registers) in machine.registers.chunks(4).enumerate() {
+        let line = registers
+            .iter()
+            .enumerate()
+            .map(|(i, &r)| format!("R{} x{:04X}", row * 4 + i, r as u16))
+            .collect::<Vec<_>>()
+            .join("  ");
+        output.push_str(&line);
+        output.push('\n');
+    }
+
+    let privilege = machine.psr >> 15;
+    let nzp = format!(
+        "{}{}{}",
+        if machine.psr & machine::N > 0 { 'n' } else { '-' },
+        if machine.psr & machine::Z > 0 { 'z' } else { '-' },
+        if machine.psr & machine::P > 0 { 'p' } else { '-' },
+    );
+    let _ = writeln!(output, "PC x{:04X}  PSR x{:04X}  PRIV {}  NZP {}", machine.pc, machine.psr, privilege, nzp);
+
+    let location = match machine.nearest_symbol(machine.pc) {
+        Some((label, addr)) if addr == machine.pc => format!("{label}: "),
+        Some((label, addr)) => format!("{label}+{}: ", machine.pc - addr),
+        None => String::new(),
+    };
+    let _ = writeln!(output, "x{:04X} {}{}", machine.pc, location, disassemble(machine, machine.pc));
+    if let Some(location) = machine.source_location(machine.pc) {
+        let text = machine.source_line(location).unwrap_or("").trim();
+        let _ = writeln!(output, "{}: {}", machine.describe_location(location), text);
+    }
+    output
+}
+
+fn print_variable(machine: &Machine, name: &str) -> String {
+    // Locals come first, so they shadow globals
+    let variables = backtrace::visible_variables(machine);
+    let Some((variable, address)) = variables.iter().find(|(v, _)| v.name == name) else {
+        return format!("Error: No variable named '{}' in scope\n", name);
+    };
+    let mut output = backtrace::describe_variable(machine, variable, *address);
+    output.push('\n');
+    output
+}
+
+fn step(app: &mut CerealApp) {
+    if let Err(e) = app.step() {
+        app.command_output.push_str(&format!("Error: {}\n", e));
+    }
+}
+
+/// Runs to the next source line, stepping into calls unless `over_calls` is set
+fn source_step(app: &mut CerealApp, over_calls: bool) {
+    if app.machine.line_table.is_empty() {
+        app.command_output.push_str("Error: No line information loaded, assemble or compile with -g\n");
+        return;
+    }
+    app.next_stop = None;
+    app.source_step = Some(SourceStep::new(&app.machine, over_calls));
+    app.execution_state = ExecutionState::Running;
+}
+
 fn assemble<'a>(mut words: impl Iterator<Item = &'a str>) -> String {
     let Some(mut output_path) = words.next().map(String::from) else { return HELP_MESSAGES["as"].to_string(); };
     output_path.push_str(".obj");
@@ -234,4 +969,61 @@ fn load_from_filename(filename: &str, machine: &mut Machine) -> String {
         Ok(()) => format!("Loading object file {filename}: code and data ...  symbols ...  file and line numbers ... "),
         Err(e) => format!("Error loading file '{filename}': {e:?}"),
     }
-}
\ No newline at end of file
+}
+
+/// Loads `$readmemh` style files (`@addr` followed by whitespace separated words) as well as files
+/// with one `addr: value` pair per line. The whole file is parsed before memory is touched.
+fn load_hex(filename: &str, machine: &mut Machine) -> String {
+    fn parse_hex(word: &str) -> Option<u16> {
+        let digits = word
+            .strip_prefix("0x")
+            .or_else(|| word.strip_prefix('x'))
+            .unwrap_or(word);
+        u16::from_str_radix(digits, 16).ok()
+    }
+
+    let Ok(contents) = std::fs::read_to_string(filename) else {
+        return format!("Cannot find file '{filename}'");
+    };
+
+    let mut writes = Vec::new();
+    let mut addr = 0u32;
+    for (i, line) in contents.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split("//").next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some((address, value)) = line.split_once(':') {
+            let (Some(address), Some(value)) = (parse_hex(address.trim()), parse_hex(value.trim())) else {
+                return format!("Error loading file '{filename}': invalid 'addr: value' pair on line {line_number}");
+            };
+            writes.push((address, value));
+            continue;
+        }
+
+        for word in line.split_whitespace() {
+            if let Some(address) = word.strip_prefix('@') {
+                let Some(address) = parse_hex(address) else {
+                    return format!("Error loading file '{filename}': invalid address '{word}' on line {line_number}");
+                };
+                addr = address as u32;
+            } else {
+                let Some(value) = parse_hex(word) else {
+                    return format!("Error loading file '{filename}': invalid hex word '{word}' on line {line_number}");
+                };
+                if addr > u16::MAX as u32 {
+                    return format!("Error loading file '{filename}': address past xFFFF on line {line_number}");
+                }
+                writes.push((addr as u16, value));
+                addr += 1;
+            }
+        }
+    }
+
+    for &(address, value) in &writes {
+        machine.memory[address as usize] = value;
+    }
+    format!("Loaded {} words from hex file {filename}", writes.len())
+}
@@ -0,0 +1,200 @@
+use super::bpred;
+use super::{Instruction, InstructionType};
+
+/// Stall penalties, in cycles, of the pipeline the cycle estimate models
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PipelineModel {
+    /// Cycles to fill the pipeline before the first instruction completes
+    pub fill_cycles: u64,
+    pub load_use_penalty: u64,
+    pub mispredict_penalty: u64,
+}
+
+impl Default for PipelineModel {
+    /// The classic five stage pipeline
+    fn default() -> Self {
+        PipelineModel {
+            fill_cycles: 4,
+            load_use_penalty: 1,
+            mispredict_penalty: 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Counters {
+    pub instructions: u64,
+    pub loads: u64,
+    pub stores: u64,
+    pub branches_taken: u64,
+    pub branches_not_taken: u64,
+    pub jumps: u64,
+    pub traps: u64,
+    pub load_use_stalls: u64,
+    /// Branches and jumps the branch predictor covers that fetched the wrong instruction
+    pub mispredicts: u64,
+    /// TRAP, RTI and JSRR, which are never predicted so always flush the pipeline
+    pub unpredicted_redirects: u64,
+    /// The register the previous instruction loaded into, if it was a load
+    last_load: Option<u8>,
+}
+
+impl Counters {
+    /// Records an executed instruction, and whether the front end would have fetched the wrong
+    /// instruction after it
+    pub(super) fn record(&mut self, instruction: Instruction, mispredicted: bool) {
+        use InstructionType::*;
+
+        self.instructions += 1;
+        if self.last_load.is_some_and(|rd| reads_load_result(instruction, rd)) {
+            self.load_use_stalls += 1;
+        }
+        self.last_load = None;
+
+        if mispredicted && bpred::is_predicted(instruction.ty) {
+            self.mispredicts += 1;
+        } else if mispredicted {
+            self.unpredicted_redirects += 1;
+        }
+
+        match instruction.ty {
+            Ldr => {
+                self.loads += 1;
+                self.last_load = Some(instruction.rd);
+            }
+            Str => self.stores += 1,
+            Jsrr | Jsr | Jmpr | Jmp | Rti => self.jumps += 1,
+            Trap => self.traps += 1,
+            _ => {}
+        }
+    }
+
+    pub(super) fn record_branch(&mut self, taken: bool) {
+        if taken {
+            self.branches_taken += 1;
+        } else {
+            self.branches_not_taken += 1;
+        }
+    }
+
+    pub fn cycles(&self, model: &PipelineModel) -> u64 {
+        if self.instructions == 0 {
+            return 0;
+        }
+        self.instructions
+            + model.fill_cycles
+            + self.load_use_stalls * model.load_use_penalty
+            + (self.mispredicts + self.unpredicted_redirects) * model.mispredict_penalty
+    }
+}
+
+pub(super) fn is_branch(ty: InstructionType) -> bool {
+    use InstructionType::*;
+    matches!(ty, Brp | Brz | Brzp | Brn | Brnp | Brnz | Brnzp)
+}
+
+/// Whether `instruction` needs the value a load just wrote to `rd` before it could be bypassed
+fn reads_load_result(instruction: Instruction, rd: u8) -> bool {
+    use InstructionType::*;
+    let reads_rs = matches!(
+        instruction.ty,
+        Add | Mul | Sub | Div | Addi | Mod | And | Not | Or | Xor | Andi | Ldr | Str | Cmp | Cmpu
+            | Cmpi | Cmpiu | Sll | Sra | Srl | Jsrr | Jmpr
+    );
+    // Store data can be bypassed into the memory stage, so only the address register stalls
+    let reads_rt = matches!(instruction.ty, Add | Mul | Sub | Div | Mod | And | Or | Xor | Cmp | Cmpu);
+
+    (reads_rs && instruction.rs == rd)
+        || (reads_rt && instruction.rt == rd)
+        || (matches!(instruction.ty, Hiconst) && instruction.rd == rd)
+        || (matches!(instruction.ty, Rti) && rd == 7)
+        // Loads set the condition codes too
+        || is_branch(instruction.ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::bpred::BranchPredictor;
+    use crate::simulator::machine::Machine;
+
+    fn instruction(ty: InstructionType, rd: u8, rs: u8, rt: u8) -> Instruction {
+        Instruction { ty, rd, rs, rt, immediate: 0 }
+    }
+
+    fn load_use_stalls(second: Instruction) -> u64 {
+        let mut counters = Counters::default();
+        counters.record(instruction(InstructionType::Ldr, 1, 6, 0), false);
+        counters.record(second, false);
+        counters.load_use_stalls
+    }
+
+    #[test]
+    fn load_then_dependent_alu_stalls() {
+        assert_eq!(load_use_stalls(instruction(InstructionType::Add, 2, 3, 1)), 1);
+        assert_eq!(load_use_stalls(instruction(InstructionType::Addi, 2, 1, 0)), 1);
+        assert_eq!(load_use_stalls(instruction(InstructionType::Add, 2, 3, 4)), 0);
+    }
+
+    #[test]
+    fn load_then_store_data_does_not_stall() {
+        assert_eq!(load_use_stalls(instruction(InstructionType::Str, 0, 6, 1)), 0);
+        // The address is needed in the execute stage
+        assert_eq!(load_use_stalls(instruction(InstructionType::Str, 0, 1, 2)), 1);
+    }
+
+    #[test]
+    fn load_then_branch_stalls_on_condition_codes() {
+        assert_eq!(load_use_stalls(instruction(InstructionType::Brz, 0, 0, 0)), 1);
+    }
+
+    /// Steps TRAP x20, then RTI back, then JSRR R0 to x0010
+    fn run_unpredicted_redirects(machine: &mut Machine) {
+        machine.memory[0x0000] = 0xF020;
+        machine.memory[0x0001] = 0x4000;
+        machine.memory[0x8020] = 0x8000;
+        machine.registers[0] = 0x0010;
+        machine.pc = 0;
+        machine.psr = 0;
+        for _ in 0..3 {
+            machine.step(&mut None).unwrap();
+        }
+        assert_eq!(machine.pc, 0x0010);
+    }
+
+    #[test]
+    fn trap_rti_jsrr_without_predictor() {
+        let mut machine = Machine::new();
+        run_unpredicted_redirects(&mut machine);
+        let counters = &machine.counters;
+        assert_eq!((counters.traps, counters.jumps), (1, 2));
+        assert_eq!((counters.mispredicts, counters.unpredicted_redirects), (0, 3));
+    }
+
+    #[test]
+    fn trap_rti_jsrr_with_predictor() {
+        let mut machine = Machine::new();
+        machine.branch_predictor = BranchPredictor::new(16);
+        run_unpredicted_redirects(&mut machine);
+        assert_eq!((machine.counters.mispredicts, machine.counters.unpredicted_redirects), (0, 3));
+        assert_eq!(machine.branch_predictor.as_ref().unwrap().predictions, 0);
+    }
+
+    #[test]
+    fn cycles() {
+        let counters = Counters {
+            instructions: 10,
+            load_use_stalls: 2,
+            mispredicts: 1,
+            unpredicted_redirects: 1,
+            ..Default::default()
+        };
+        let model = PipelineModel {
+            fill_cycles: 2,
+            load_use_penalty: 3,
+            mispredict_penalty: 5,
+        };
+        assert_eq!(counters.cycles(&model), 10 + 2 + 2 * 3 + 2 * 5);
+        assert_eq!(Counters::default().cycles(&model), 0);
+    }
+}
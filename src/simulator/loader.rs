@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::io::{self, Write};
 
 use super::decode::{decode, InvalidInstructionError};
+use super::machine::{SourceFile, SourceLocation, Variable, VariableScope, VariableType};
 use super::Machine;
 
 fn print_instruction(word: u16, trace: &mut dyn Write) -> io::Result<()> {
@@ -84,7 +85,7 @@ pub(super) fn load(
     machine: &mut Machine,
     mut trace: Option<&mut dyn Write>,
 ) -> Result<(), LoadError> {
-    use crate::{CODE_HEADER, DATA_HEADER, FILE_HEADER, LINE_HEADER, SYMBOL_HEADER};
+    use crate::{CODE_HEADER, DATA_HEADER, FILE_HEADER, LINE_HEADER, SYMBOL_HEADER, VARIABLE_HEADER};
 
     let mut reader = Reader {
         bytes,
@@ -93,7 +94,8 @@ pub(super) fn load(
     };
 
     let mut label_addresses = HashMap::new();
-    let mut file_names = Vec::new();
+    // File indices in line records are local to this object file
+    let mut file_indices = Vec::new();
 
     let bytes = &mut &*bytes;
     while !bytes.is_empty() {
@@ -161,12 +163,29 @@ pub(super) fn load(
             FILE_HEADER => {
                 let nbytes = reader.read_word()?;
                 let file_name = reader.read_str(nbytes)?;
-                file_names.push(file_name);
+                let lines = std::fs::read_to_string(file_name)
+                    .ok()
+                    .map(|source| source.lines().map(String::from).collect());
+                let file = SourceFile {
+                    name: file_name.to_string(),
+                    lines,
+                };
+                // Reloading a program replaces its files instead of adding them again
+                match machine.source_files.iter().position(|f| f.name == file_name) {
+                    Some(index) => {
+                        machine.source_files[index] = file;
+                        file_indices.push(index);
+                    }
+                    None => {
+                        file_indices.push(machine.source_files.len());
+                        machine.source_files.push(file);
+                    }
+                }
                 if let Some(trace) = trace.as_deref_mut() {
                     let _ = writeln!(
                         trace,
                         "; File index ({}) file: {}",
-                        file_names.len() - 1,
+                        file_indices.len() - 1,
                         file_name
                     );
                 }
@@ -175,6 +194,9 @@ pub(super) fn load(
                 let addr = reader.read_word()?;
                 let line = reader.read_word()?;
                 let file_index = reader.read_word()?;
+                if let Some(&file) = file_indices.get(file_index as usize) {
+                    machine.line_table.insert(addr, SourceLocation { file, line });
+                }
                 if let Some(trace) = trace.as_deref_mut() {
                     let _ = writeln!(
                         trace,
@@ -183,6 +205,36 @@ pub(super) fn load(
                     );
                 }
             }
+            VARIABLE_HEADER => {
+                let kind = reader.read_word()?;
+                let procedure = reader.read_word()?;
+                let value = reader.read_word()?;
+                let type_code = reader.read_word()?;
+                let nbytes = reader.read_word()?;
+                let name = reader.read_str(nbytes)?;
+
+                let scope = match kind {
+                    0 => Some(VariableScope::Global { address: value }),
+                    1 => Some(VariableScope::Local { procedure, offset: value as i16 }),
+                    _ => None,
+                };
+                let ty = match type_code {
+                    0 => Some(VariableType::Int),
+                    _ => None,
+                };
+                // Skip kinds of variables we don't know how to show
+                if let (Some(scope), Some(ty)) = (scope, ty) {
+                    machine.variables.retain(|v| v.name != name || v.scope != scope);
+                    machine.variables.push(Variable {
+                        name: name.to_string(),
+                        ty,
+                        scope,
+                    });
+                }
+                if let Some(trace) = trace.as_deref_mut() {
+                    let _ = writeln!(trace, "; Variable {} kind {} procedure {:x} value {:x} type {}", name, kind, procedure, value, type_code);
+                }
+            }
             _ => {
                 if let Some(trace) = trace.as_deref_mut() {
                     let _ = writeln!(trace, ";; ERROR: Invalid header: {:x}", word);
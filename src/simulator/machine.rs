@@ -1,14 +1,108 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use super::bpred::{self, BranchPredictor};
+use super::counters::{self, Counters, PipelineModel};
 use super::{decode, Trace, Instruction, InstructionType};
 
-#[allow(dead_code)]
 #[derive(Debug)]
 pub struct ExecutionError {
     pub kind: ExecutionErrorKind,
     pub pc: u16,
 }
 
-#[allow(dead_code)]
+impl std::fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.kind {
+            ExecutionErrorKind::PcRollover => {
+                write!(f, "PC rolled over out of a code section after x{:04X}", self.pc)
+            }
+            ExecutionErrorKind::InvalidJump { address } => write!(
+                f,
+                "Jump at x{:04X} to x{:04X}, which is not in a code section",
+                self.pc, address
+            ),
+            ExecutionErrorKind::InvalidMemoryAccess {
+                address,
+                lacks_privilege,
+                is_read,
+            } => {
+                let access = if is_read { "read" } else { "write" };
+                let reason = if lacks_privilege {
+                    "the PSR's privilege bit is not set"
+                } else {
+                    "the address is in a code section"
+                };
+                write!(
+                    f,
+                    "Illegal memory {} of x{:04X} at x{:04X}: {}",
+                    access, address, self.pc, reason
+                )
+            }
+            ExecutionErrorKind::InvalidInstruction => {
+                write!(f, "Invalid instruction at x{:04X}", self.pc)
+            }
+        }
+    }
+}
+
+/// The data memory access made by the last instruction
+#[derive(Copy, Clone, Debug)]
+pub struct MemoryAccess {
+    pub address: u16,
+    pub is_read: bool,
+    pub old_value: u16,
+    pub new_value: u16,
+}
+
+/// A source file named in the debug info of a loaded object file
+pub struct SourceFile {
+    pub name: String,
+    /// The file's contents, if it could be read when the object file was loaded
+    pub lines: Option<Vec<String>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// Index into `Machine::source_files`
+    pub file: usize,
+    /// One based, like the line numbers in the object file
+    pub line: u16,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VariableType {
+    Int,
+}
+
+impl std::fmt::Display for VariableType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VariableType::Int => write!(f, "int"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VariableScope {
+    Global { address: u16 },
+    /// Relative to the frame pointer of the C procedure starting at `procedure`
+    Local { procedure: u16, offset: i16 },
+}
+
+/// A C variable named in the debug info of a loaded object file
+pub struct Variable {
+    pub name: String,
+    pub ty: VariableType,
+    pub scope: VariableScope,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed,
+    /// The instruction cleared the run bit of the MCR
+    Halted,
+}
+
 #[derive(Debug)]
 pub enum ExecutionErrorKind {
     PcRollover,
@@ -27,14 +121,149 @@ pub const P: u16 = 1;
 pub const Z: u16 = 2;
 pub const N: u16 = 4;
 const OS_MODE: u16 = 0x8000;
+const KBSR_ADDR: u16 = 0xFE00;
+const KBDR_ADDR: u16 = 0xFE02;
+const ADSR_ADDR: u16 = 0xFE04;
+const ADDR_ADDR: u16 = 0xFE06;
+const TSR_ADDR: u16 = 0xFE08;
+const TIR_ADDR: u16 = 0xFE0A;
+const VDCR_ADDR: u16 = 0xFE0C;
+const VDCR_RESET: u16 = 1;
+const VDCR_PRESENT: u16 = 2;
+const MCR_ADDR: u16 = 0xFFEE;
+const MCR_RUN: u16 = 0x8000;
 const MEMORY_SIZE: usize = 1 << 16;
 
+pub const VIDEO_WIDTH: usize = 128;
+pub const VIDEO_HEIGHT: usize = 124;
+const VIDEO_START: usize = 0xC000;
+const VIDEO_END: usize = VIDEO_START + VIDEO_WIDTH * VIDEO_HEIGHT;
+
+/// In step driven mode we pretend the machine runs at 1 MHz
+const STEPS_PER_MILLISECOND: u64 = 1000;
+
+/// What the timer interval in TIR is measured against
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimerMode {
+    /// Deterministic: counts executed instructions
+    #[default]
+    Steps,
+    /// Counts real time, for interactive use
+    WallClock,
+}
+
+struct Timer {
+    mode: TimerMode,
+    interval: u16,
+    steps: u64,
+    last_fired: Instant,
+    fired: bool,
+}
+
+impl Timer {
+    fn new(mode: TimerMode) -> Self {
+        Timer {
+            mode,
+            interval: 0,
+            steps: 0,
+            last_fired: Instant::now(),
+            fired: false,
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.mode != TimerMode::Steps || self.interval == 0 {
+            return;
+        }
+        self.steps += 1;
+        if self.steps >= self.interval as u64 * STEPS_PER_MILLISECOND {
+            self.steps = 0;
+            self.fired = true;
+        }
+    }
+
+    /// TSR has its high bit set if the interval has elapsed since it was last read
+    fn read_status(&mut self) -> u16 {
+        if self.mode == TimerMode::WallClock && self.interval > 0 {
+            let interval = Duration::from_millis(self.interval as u64);
+            if self.last_fired.elapsed() >= interval {
+                self.last_fired = Instant::now();
+                self.fired = true;
+            }
+        }
+        let status = if self.fired { 0x8000 } else { 0 };
+        self.fired = false;
+        status
+    }
+
+    fn set_interval(&mut self, interval: u16) {
+        *self = Timer {
+            interval,
+            ..Timer::new(self.mode)
+        };
+    }
+}
+
+/// State behind the memory-mapped device registers in xFE00-xFFFF
+pub struct Devices {
+    pub keyboard: VecDeque<u8>,
+    console_output: Vec<u8>,
+    timer: Timer,
+    mcr: u16,
+    halt_requested: bool,
+    /// The last frame presented through the VDCR, if the program has ever presented one
+    display: Option<Vec<u16>>,
+    pub frames: u64,
+}
+
+impl Default for Devices {
+    fn default() -> Self {
+        Devices::new(TimerMode::default())
+    }
+}
+
+impl Devices {
+    pub fn new(timer_mode: TimerMode) -> Self {
+        Devices {
+            keyboard: Default::default(),
+            console_output: Default::default(),
+            timer: Timer::new(timer_mode),
+            mcr: MCR_RUN,
+            halt_requested: false,
+            display: None,
+            frames: 0,
+        }
+    }
+
+    pub fn timer_mode(&self) -> TimerMode {
+        self.timer.mode
+    }
+
+    /// Takes everything written to the display data register since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.console_output)
+    }
+}
+
+/// Code may only live in user code (x0000-x1FFF) and OS code (x8000-x9FFF)
+pub fn is_executable(address: u16) -> bool {
+    address < 0x2000 || (0x8000..0xA000).contains(&address)
+}
+
 pub struct Machine {
     pub pc: u16,
     pub psr: u16,
     pub registers: [i16; 8],
     pub memory: Box<[u16; MEMORY_SIZE]>,
     pub symbols: HashMap<String, u16>,
+    pub source_files: Vec<SourceFile>,
+    pub line_table: BTreeMap<u16, SourceLocation>,
+    pub variables: Vec<Variable>,
+    pub devices: Devices,
+    pub counters: Counters,
+    pub pipeline: PipelineModel,
+    pub branch_predictor: Option<BranchPredictor>,
+    pub last_access: Option<MemoryAccess>,
 }
 
 impl Default for Machine {
@@ -50,6 +279,14 @@ impl Default for Machine {
             registers: [0; 8],
             memory,
             symbols: Default::default(),
+            source_files: Default::default(),
+            line_table: Default::default(),
+            variables: Default::default(),
+            devices: Default::default(),
+            counters: Default::default(),
+            pipeline: Default::default(),
+            branch_predictor: None,
+            last_access: None,
         }
     }
 }
@@ -67,10 +304,96 @@ impl Machine {
         }
         self.pc = 0x8200;
         self.psr = OS_MODE | N;
+        // Debug info describes the memory that was just cleared
+        self.line_table.clear();
+        self.source_files.clear();
+        self.variables.clear();
+        self.devices = Devices::new(self.devices.timer_mode());
+        self.counters = Default::default();
+        if let Some(predictor) = &mut self.branch_predictor {
+            *predictor = BranchPredictor::new(predictor.size()).expect("Size was already valid");
+        }
     }
 
-    pub fn pc(&self) -> u16 {
-        self.pc
+    /// Reads memory as a load instruction would, including any device side effects
+    fn read(&mut self, address: u16) -> u16 {
+        match address {
+            KBSR_ADDR => if self.devices.keyboard.is_empty() { 0 } else { 0x8000 },
+            KBDR_ADDR => self.devices.keyboard.pop_front().map_or(0, u16::from),
+            // The console is always ready to accept another character
+            ADSR_ADDR => 0x8000,
+            TSR_ADDR => self.devices.timer.read_status(),
+            TIR_ADDR => self.devices.timer.interval,
+            MCR_ADDR => self.devices.mcr,
+            _ => self.memory[address as usize],
+        }
+    }
+
+    /// Writes memory as a store instruction would, including any device side effects
+    fn write(&mut self, address: u16, value: u16) {
+        match address {
+            ADDR_ADDR => self.devices.console_output.push(value as u8),
+            TIR_ADDR => self.devices.timer.set_interval(value),
+            VDCR_ADDR => {
+                if value & VDCR_PRESENT > 0 {
+                    self.devices.display = Some(self.memory[VIDEO_START..VIDEO_END].to_vec());
+                    self.devices.frames += 1;
+                }
+                if value & VDCR_RESET > 0 {
+                    self.memory[VIDEO_START..VIDEO_END].fill(0);
+                }
+            }
+            MCR_ADDR => {
+                self.devices.mcr = value;
+                self.devices.halt_requested = value & MCR_RUN == 0;
+            }
+            _ => self.memory[address as usize] = value,
+        }
+    }
+
+    /// The pixels currently on the display. Programs that never present a frame through the VDCR
+    /// are shown video memory directly.
+    pub fn frame(&self) -> &[u16] {
+        match &self.devices.display {
+            Some(display) => display,
+            None => &self.memory[VIDEO_START..VIDEO_END],
+        }
+    }
+
+    pub fn source_location(&self, address: u16) -> Option<SourceLocation> {
+        self.line_table.get(&address).copied()
+    }
+
+    /// `file:line` for a location from the line table
+    pub fn describe_location(&self, location: SourceLocation) -> String {
+        format!("{}:{}", self.source_files[location.file].name, location.line)
+    }
+
+    /// The text of the source line at `location`, if its file could be read
+    pub fn source_line(&self, location: SourceLocation) -> Option<&str> {
+        let lines = self.source_files[location.file].lines.as_ref()?;
+        lines.get((location.line as usize).checked_sub(1)?).map(|l| &**l)
+    }
+
+    /// All symbols at `address`, sorted by name
+    pub fn labels_at(&self, address: u16) -> Vec<&str> {
+        let mut labels = self
+            .symbols
+            .iter()
+            .filter(|(_, &a)| a == address)
+            .map(|(label, _)| &**label)
+            .collect::<Vec<_>>();
+        labels.sort_unstable();
+        labels
+    }
+
+    /// The symbol closest to, but not after, `address`
+    pub fn nearest_symbol(&self, address: u16) -> Option<(&str, u16)> {
+        self.symbols
+            .iter()
+            .filter(|(_, &a)| a <= address)
+            .max_by(|(l1, a1), (l2, a2)| a1.cmp(a2).then(l2.cmp(l1)))
+            .map(|(label, &a)| (&**label, a))
     }
 
     fn os_mode(&self) -> bool {
@@ -121,7 +444,7 @@ impl Machine {
                 machine.pc + 1
             };
 
-            if (0x2000..0x8000).contains(&pc) || pc >= 0xA000 {
+            if !is_executable(pc) {
                 Err(ExecutionError {
                     kind: ExecutionErrorKind::InvalidJump { address: pc },
                     pc: machine.pc,
@@ -131,23 +454,15 @@ impl Machine {
             }
         }
 
-        fn jump_to(
-            machine: &mut Machine,
-            address: u16,
-            from_pc_plus_one: bool,
-        ) -> Result<(), ExecutionError> {
-            if (0x2000..0x8000).contains(&address) || address >= 0xA000 {
+        // Checked before the instruction changes any state, so a failed jump can't be continued past
+        fn jump_target(machine: &Machine, address: u16) -> Result<u16, ExecutionError> {
+            if !is_executable(address) {
                 Err(ExecutionError {
                     kind: ExecutionErrorKind::InvalidJump { address },
-                    pc: if from_pc_plus_one {
-                        machine.pc + 1
-                    } else {
-                        machine.pc
-                    },
+                    pc: machine.pc,
                 })
             } else {
-                machine.pc = address;
-                Ok(())
+                Ok(address)
             }
         }
 
@@ -222,9 +537,15 @@ impl Machine {
             instruction: Instruction,
         ) -> Result<(), ExecutionError> {
             let address =
-                (machine.registers[instruction.rs as usize] + instruction.immediate) as u16;
+                machine.registers[instruction.rs as usize].wrapping_add(instruction.immediate) as u16;
             check_address(machine, address, true)?;
-            let value = machine.memory[address as usize] as i16;
+            let value = machine.read(address) as i16;
+            machine.last_access = Some(MemoryAccess {
+                address,
+                is_read: true,
+                old_value: value as u16,
+                new_value: value as u16,
+            });
             write_to_register(machine, trace, instruction.rd, value);
             machine.pc += 1;
 
@@ -242,10 +563,16 @@ impl Machine {
             instruction: Instruction,
         ) -> Result<(), ExecutionError> {
             let address =
-                (machine.registers[instruction.rs as usize] + instruction.immediate) as u16;
+                machine.registers[instruction.rs as usize].wrapping_add(instruction.immediate) as u16;
             check_address(machine, address, false)?;
             let value = machine.registers[instruction.rt as usize] as u16;
-            machine.memory[address as usize] = value;
+            machine.last_access = Some(MemoryAccess {
+                address,
+                is_read: false,
+                old_value: machine.memory[address as usize],
+                new_value: value,
+            });
+            machine.write(address, value);
             machine.pc += 1;
 
             if let Some(trace) = trace {
@@ -256,6 +583,20 @@ impl Machine {
             Ok(())
         }
 
+        fn checks_own_target(ty: InstructionType) -> bool {
+            use InstructionType::*;
+            matches!(ty, Nop | Brp | Brz | Brzp | Brn | Brnp | Brnz | Brnzp | Jsrr | Jsr | Jmpr | Jmp | Trap | Rti)
+        }
+
+        // Jump style instructions check their own targets, everything else must not run off
+        // the end of a code section
+        if !checks_own_target(instruction.ty) && !is_executable(self.pc.wrapping_add(1)) {
+            return Err(ExecutionError {
+                kind: ExecutionErrorKind::PcRollover,
+                pc: self.pc,
+            });
+        }
+
         match instruction.ty {
             InstructionType::Nop => self.pc = branch_on(self, instruction, 0)?,
             InstructionType::Brp => self.pc = branch_on(self, instruction, P)?,
@@ -319,24 +660,24 @@ impl Machine {
                 ((a as u16) >> (b as u16)) as i16
             }),
             InstructionType::Jsrr => {
-                let rs = self.registers[instruction.rs as usize] as u16;
-                self.pc += 1;
-                write_to_register(self, trace, 7, self.pc as i16);
-                jump_to(self, rs, true)?;
+                let target = jump_target(self, self.registers[instruction.rs as usize] as u16)?;
+                write_to_register(self, trace, 7, self.pc.wrapping_add(1) as i16);
+                self.pc = target;
             }
             InstructionType::Jsr => {
                 let immediate = instruction.immediate as u16;
-                self.pc += 1;
-                write_to_register(self, trace, 7, self.pc as i16);
-                jump_to(self, (self.pc & OS_MODE) | (immediate << 4), true)?;
+                let return_address = self.pc.wrapping_add(1);
+                let target = jump_target(self, (return_address & OS_MODE) | (immediate << 4))?;
+                write_to_register(self, trace, 7, return_address as i16);
+                self.pc = target;
             }
             InstructionType::Jmpr => {
                 let rs = self.registers[instruction.rs as usize] as u16;
-                jump_to(self, rs, false)?;
+                self.pc = jump_target(self, rs)?;
             }
             InstructionType::Jmp => {
                 let immediate = instruction.immediate;
-                jump_to(self, (self.pc as i16 + 1 + immediate) as u16, false)?;
+                self.pc = jump_target(self, self.pc.wrapping_add(1).wrapping_add(immediate as u16))?;
             }
             InstructionType::Trap => {
                 self.pc += 1;
@@ -346,25 +687,17 @@ impl Machine {
                 self.psr |= OS_MODE;
             }
             InstructionType::Rti => {
+                self.pc = jump_target(self, self.registers[7] as u16)?;
                 self.psr &= !OS_MODE;
-                jump_to(self, self.registers[7] as u16, false)?;
             }
         }
 
-        // All jump style instructions check their jump location ahead of time
-        // So here we just check if we ran over from the previous instruction
-        if self.pc >= 0x2000 && self.pc < 0x8000 || self.pc >= 0xA000 {
-            Err(ExecutionError {
-                kind: ExecutionErrorKind::PcRollover,
-                pc: self.pc - 1,
-            })
-        } else {
-            Ok(())
-        }
+        Ok(())
     }
 
-    pub fn step(&mut self, trace: &mut Option<Trace>) -> Result<(), ExecutionError> {
+    pub fn step(&mut self, trace: &mut Option<Trace>) -> Result<StepOutcome, ExecutionError> {
         let pc = self.pc;
+        self.last_access = None;
         let instruction_word = self.memory[pc as usize];
         let instruction = decode::decode(instruction_word, trace).map_err(|_| ExecutionError {
             kind: ExecutionErrorKind::InvalidInstruction,
@@ -377,7 +710,44 @@ impl Machine {
         }
 
         self.execute_instruction(instruction, trace)?;
+        self.devices.timer.tick();
 
-        Ok(())
+        let redirected = self.pc != pc.wrapping_add(1);
+        if counters::is_branch(instruction.ty) {
+            self.counters.record_branch(redirected);
+        }
+        // Without a branch predictor the next sequential instruction is always fetched
+        let mispredicted = match &mut self.branch_predictor {
+            Some(predictor) if bpred::is_predicted(instruction.ty) => predictor.resolve(pc, self.pc),
+            _ => redirected,
+        };
+        self.counters.record(instruction, mispredicted);
+
+        if std::mem::take(&mut self.devices.halt_requested) {
+            Ok(StepOutcome::Halted)
+        } else {
+            Ok(StepOutcome::Executed)
+        }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_address_wraps_into_os_memory() {
+        let mut machine = Machine::new();
+        // STR R0, R6, #1
+        machine.memory[0] = 0x7181;
+        machine.registers[6] = 0x7FFF;
+        machine.pc = 0;
+        machine.psr = 0;
+
+        let error = machine.step(&mut None).unwrap_err();
+        assert_eq!(error.pc, 0);
+        assert!(matches!(
+            error.kind,
+            ExecutionErrorKind::InvalidMemoryAccess { address: 0x8000, lacks_privilege: true, is_read: false }
+        ));
+    }
+}
@@ -2,14 +2,23 @@ use std::collections::BTreeMap;
 use std::io::{self, Write};
 use std::path::PathBuf;
 
+mod backtrace;
+mod bpred;
+mod breakpoint;
+mod capture;
 mod command;
+mod counters;
 mod decode;
 mod loader;
 mod machine;
+mod source_step;
 
-use machine::{Machine, ExecutionError};
+use machine::{Devices, Machine, ExecutionError, StepOutcome};
+pub use capture::CaptureMode;
+pub use counters::PipelineModel;
+pub use machine::TimerMode;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 enum InstructionType {
     Nop,
@@ -94,6 +103,7 @@ impl InstructionType {
     }
 }
 
+#[derive(Copy, Clone)]
 struct Instruction {
     ty: InstructionType,
     rd: u8,
@@ -102,6 +112,36 @@ struct Instruction {
     immediate: i16,
 }
 
+impl Instruction {
+    /// The absolute address a PC-relative branch or jump at `pc` would go to
+    fn target(&self, pc: u16) -> Option<u16> {
+        use InstructionType::*;
+        match self.ty {
+            Brp | Brz | Brzp | Brn | Brnp | Brnz | Brnzp | Jmp => {
+                Some(pc.wrapping_add(1).wrapping_add(self.immediate as u16))
+            }
+            Jsr => Some((pc.wrapping_add(1) & 0x8000) | ((self.immediate as u16) << 4)),
+            _ => None,
+        }
+    }
+}
+
+/// Disassembles the word at `address`, showing branch and jump targets as absolute addresses
+fn disassemble(machine: &Machine, address: u16) -> String {
+    let word = machine.memory[address as usize];
+    let Ok(instruction) = decode::decode(word, &mut None) else {
+        return format!(".fill x{word:04X}");
+    };
+    let Some(target) = instruction.target(address) else {
+        return instruction.to_string();
+    };
+    let mut text = format!("{} x{target:04X}", instruction.ty.to_mnemonic());
+    if let Some(label) = machine.labels_at(target).first() {
+        text.push_str(&format!(" <{label}>"));
+    }
+    text
+}
+
 impl std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self.ty {
@@ -228,39 +268,35 @@ impl std::fmt::Display for Instruction {
             ),
             InstructionType::Cmp => write!(
                 f,
-                "{} r{}, r{}, r{}",
+                "{} r{}, r{}",
                 self.ty.to_mnemonic(),
-                self.rd,
                 self.rs,
                 self.rt
             ),
             InstructionType::Cmpu => write!(
                 f,
-                "{} r{}, r{}, r{}",
+                "{} r{}, r{}",
                 self.ty.to_mnemonic(),
-                self.rd,
                 self.rs,
                 self.rt
             ),
             InstructionType::Cmpi => write!(
                 f,
-                "{} r{}, r{}, #{}",
+                "{} r{}, #{}",
                 self.ty.to_mnemonic(),
-                self.rd,
                 self.rs,
                 self.immediate
             ),
             InstructionType::Cmpiu => write!(
                 f,
-                "{} r{}, r{}, #{}",
+                "{} r{}, #{}",
                 self.ty.to_mnemonic(),
-                self.rd,
                 self.rs,
                 self.immediate as u16
             ),
             InstructionType::Sll => write!(
                 f,
-                "{}, r{}, r{}, #{}",
+                "{} r{}, r{}, #{}",
                 self.ty.to_mnemonic(),
                 self.rd,
                 self.rs,
@@ -268,7 +304,7 @@ impl std::fmt::Display for Instruction {
             ),
             InstructionType::Sra => write!(
                 f,
-                "{}, r{}, r{}, #{}",
+                "{} r{}, r{}, #{}",
                 self.ty.to_mnemonic(),
                 self.rd,
                 self.rs,
@@ -276,7 +312,7 @@ impl std::fmt::Display for Instruction {
             ),
             InstructionType::Srl => write!(
                 f,
-                "{}, r{}, r{}, #{}",
+                "{} r{}, r{}, #{}",
                 self.ty.to_mnemonic(),
                 self.rd,
                 self.rs,
@@ -357,6 +393,10 @@ pub struct Options {
     pub headless: bool,
     pub from_directory: Option<PathBuf>,
     pub startup_script: Option<PathBuf>,
+    pub timer_mode: TimerMode,
+    pub capture_path: Option<PathBuf>,
+    pub capture_mode: CaptureMode,
+    pub pipeline: PipelineModel,
 }
 
 use eframe::egui;
@@ -375,8 +415,19 @@ pub(crate) struct CerealApp {
     command_index: Option<usize>,
     command_history: Vec<String>,
     command_output: String,
+    console_output: String,
+    keyboard_line: String,
     script_commands: Vec<String>,
-    breakpoints: BTreeMap<u16, String>,
+    /// Started with a script from the command line, so nobody is watching the command pane
+    scripted: bool,
+    breakpoints: BTreeMap<u16, breakpoint::Breakpoint>,
+    watchpoints: Vec<breakpoint::Watchpoint>,
+    /// The call `next` is stepping over
+    next_stop: Option<source_step::ReturnPoint>,
+    source_step: Option<source_step::SourceStep>,
+    shown_location: Option<machine::SourceLocation>,
+    checks: command::CheckCounts,
+    cumulative_checks: command::CheckCounts,
     trace: Option<Box<dyn Write>>,
     execution_state: ExecutionState,
 }
@@ -385,6 +436,7 @@ impl CerealApp {
     fn new(machine: Machine, startup_script: Option<PathBuf>) -> Self {
         let mut app = CerealApp {
             machine,
+            scripted: startup_script.is_some(),
             ..Default::default()
         };
         if let Some(path) = startup_script {
@@ -495,24 +547,23 @@ impl CerealApp {
                 });
             });
         });
-    }
 
-    fn devices(&mut self, ui: &mut egui::Ui) {
-        fn unpack(b: u16, s: u8) -> u8 {
-            ((b >> s) as u8 & ((1 << 5) - 1)) << 3
+        if let Some(location) = self.machine.source_location(self.machine.pc) {
+            ui.label(format!("Source {}", self.machine.describe_location(location)));
         }
+    }
 
-        let memory_start = 0xC000;
-        let memory_end = 0xFDFF;
-        let mut pixel_data = Vec::with_capacity(128 * 124);
-        for addr in memory_start..memory_end+1 {
-            let data = self.machine.memory[addr];
-            let color = egui::Color32::from_rgb(unpack(data, 10), unpack(data, 5), unpack(data, 0));
-            pixel_data.push(color);
-        }
+    fn devices(&mut self, ui: &mut egui::Ui) {
+        let pixel_data = self.machine.frame()
+            .iter()
+            .map(|&data| {
+                let [r, g, b] = capture::unpack_rgb555(data);
+                egui::Color32::from_rgb(r, g, b)
+            })
+            .collect();
 
         let image_data = egui::ImageData::Color(egui::ColorImage {
-            size: [128, 124], 
+            size: [machine::VIDEO_WIDTH, machine::VIDEO_HEIGHT],
             pixels: pixel_data,
         });
 
@@ -520,6 +571,33 @@ impl CerealApp {
         ui.image(&texture, [128.0 * 2.0, 124.0 * 2.0]);
     }
 
+    fn console(&mut self, ui: &mut egui::Ui) {
+        let output = self.machine.devices.take_output();
+        self.console_output.push_str(&String::from_utf8_lossy(&output));
+
+        ui.label("Console");
+        let scroll_area = egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .max_height(100.0)
+            .max_width(256.0)
+            .stick_to_bottom(true);
+        scroll_area.show(ui, |ui| {
+            ui.set_height(100.0);
+            egui::TextEdit::multiline(&mut &*self.console_output).desired_width(f32::INFINITY).show(ui);
+        });
+
+        let response = egui::TextEdit::singleline(&mut self.keyboard_line)
+            .hint_text("Keyboard input")
+            .desired_width(256.0)
+            .show(ui)
+            .response;
+        if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            self.keyboard_line.push('\n');
+            self.machine.devices.keyboard.extend(self.keyboard_line.bytes());
+            self.keyboard_line.clear();
+        }
+    }
+
     fn memory(&mut self, ui: &mut egui::Ui) {
         ui.label("Memory");
 
@@ -534,15 +612,56 @@ impl CerealApp {
             ui.set_height(400.0);
             for row in row_range {
                 let text = if row > 0xfdff {
-                    format!("Address: x{:04X} Value ???", row)
+                    format!("x{:04X} ????", row)
                 } else {
-                    format!("Address: x{:04X} Value {}", row, self.machine.memory[row])
+                    let labels = self.machine.labels_at(row as u16);
+                    let label = labels.first().map(|l| format!("{l}: ")).unwrap_or_default();
+                    format!("x{:04X} {:04X} {}{}", row, self.machine.memory[row], label, disassemble(&self.machine, row as u16))
                 };
                 ui.label(text);
             }
         });
     }
 
+    fn source(&mut self, ui: &mut egui::Ui) {
+        ui.label("Source");
+
+        let Some(location) = self.machine.source_location(self.machine.pc) else {
+            ui.label("No line information at the PC");
+            return;
+        };
+        // Only follow the PC when it moves, so the pane can still be scrolled by hand
+        let scroll_to_line = self.shown_location != Some(location);
+        self.shown_location = Some(location);
+
+        let file = &self.machine.source_files[location.file];
+        ui.label(&file.name);
+        let Some(lines) = &file.lines else {
+            ui.label("Unable to read source file");
+            return;
+        };
+
+        let scroll_area = egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .max_width(400.0)
+            .auto_shrink([false; 2])
+            .always_show_scroll(true);
+
+        scroll_area.show(ui, |ui| {
+            for (i, line) in lines.iter().enumerate() {
+                let text = egui::RichText::new(format!("{:>4} {}", i + 1, line)).monospace();
+                if i + 1 != location.line as usize {
+                    ui.label(text);
+                    continue;
+                }
+                let response = ui.label(text.background_color(ui.visuals().selection.bg_fill));
+                if scroll_to_line {
+                    response.scroll_to_me(Some(egui::Align::Center));
+                }
+            }
+        });
+    }
+
     fn show_breakpoints(&mut self, ui: &mut egui::Ui) {
         ui.label("Breakpoints");
 
@@ -556,36 +675,105 @@ impl CerealApp {
         scroll_area.show_rows(ui, row_height, self.breakpoints.len(), |ui, row_range| {
             ui.set_height(400.0);
             let iter = self.breakpoints.iter().skip(row_range.start).take(row_range.end - row_range.start);
-            for (&addr, label) in iter {
+            for (&addr, breakpoint) in iter {
                 let text = if addr > 0xfdff {
-                    format!("x{:04X} ({}) Value ???", addr, label)
+                    format!("x{:04X} {} Value ???", addr, breakpoint)
                 } else {
-                    format!("x{:04X} ({}) Value {}", addr, label, self.machine.memory[addr as usize])
+                    format!("x{:04X} {} Value {}", addr, breakpoint, self.machine.memory[addr as usize])
                 };
                 ui.label(text);
             }
         });
+
+        ui.label("Watchpoints");
+        for watchpoint in &self.watchpoints {
+            ui.label(watchpoint.to_string());
+        }
     }
 
-    fn run_frame(&mut self) -> Result<(), ExecutionError> {
-        for _ in 0..500 {
-            let mut trace = self.trace.as_ref().map(|_| Trace::new());
+    fn variables(&mut self, ui: &mut egui::Ui) {
+        ui.label("Variables");
+        for (variable, address) in backtrace::visible_variables(&self.machine) {
+            ui.label(backtrace::describe_variable(&self.machine, variable, address));
+        }
+    }
 
-            self.machine.step(&mut trace)?;
+    fn call_stack(&mut self, ui: &mut egui::Ui) {
+        ui.label("Call Stack");
+        for (i, frame) in backtrace::backtrace(&self.machine).iter().enumerate() {
+            ui.label(backtrace::describe(&self.machine, i, frame).trim_end());
+        }
+    }
 
-            if let Some(trace) = trace {
-                if let Err(e) = trace.write_to_file(self.trace.as_mut().unwrap()) {
-                    self.command_output.push_str(&format!("Failed to write to trace file: {:?}\n", e));
-                    return Ok(());
-                }
+    fn step(&mut self) -> Result<StepOutcome, ExecutionError> {
+        let mut trace = self.trace.as_ref().map(|_| Trace::new());
+
+        let pc = self.machine.pc;
+        let outcome = self.machine.step(&mut trace)?;
+
+        if let Some(trace) = trace {
+            if let Err(e) = trace.write_to_file(self.trace.as_mut().unwrap()) {
+                self.command_output.push_str(&format!("Failed to write to trace file: {:?}\n", e));
+                self.execution_state = ExecutionState::Suspended;
+            }
+        }
+
+        if let Some(access) = self.machine.last_access {
+            if self.watchpoints.iter().any(|w| w.matches(&access)) {
+                self.execution_state = ExecutionState::Suspended;
+                self.next_stop = None;
+                self.source_step = None;
+                let kind = if access.is_read { "Read" } else { "Write" };
+                self.command_output.push_str(&format!(
+                    "Watchpoint: {} of x{:04X} by instruction at x{:04X}: x{:04X} -> x{:04X}\n",
+                    kind, access.address, pc, access.old_value, access.new_value
+                ));
+            }
+        }
+
+        if outcome == StepOutcome::Halted {
+            self.execution_state = ExecutionState::Suspended;
+            self.next_stop = None;
+            self.source_step = None;
+            self.command_output.push_str(&format!("Machine halted at x{:04X}\n", self.machine.pc));
+        }
+        Ok(outcome)
+    }
+
+    fn run_frame(&mut self) -> Result<(), ExecutionError> {
+        for _ in 0..500 {
+            if let Some(source_step) = &mut self.source_step {
+                source_step.before_step(&self.machine);
+            }
+            self.step()?;
+            if self.execution_state == ExecutionState::Suspended {
+                break;
             }
 
             // Postcondition so we can move past breakpoints
-            if self.breakpoints.contains_key(&self.machine.pc) {
+            let hit = self.breakpoints
+                .get_mut(&self.machine.pc)
+                .is_some_and(|b| b.hit(&self.machine));
+            if hit {
                 self.execution_state = ExecutionState::Suspended;
+                self.next_stop = None;
+                self.source_step = None;
                 self.command_output.push_str(&format!("Hit breakpoint at x{:04X}\n", self.machine.pc));
                 break;
             }
+
+            if self.next_stop.is_some_and(|r| r.reached(&self.machine)) {
+                self.execution_state = ExecutionState::Suspended;
+                self.next_stop = None;
+                self.source_step = None;
+                break;
+            }
+
+            if self.source_step.as_mut().is_some_and(|s| s.after_step(&self.machine)) {
+                self.execution_state = ExecutionState::Suspended;
+                self.source_step = None;
+                break;
+            }
         }
         Ok(())
     }
@@ -603,6 +791,7 @@ impl eframe::App for CerealApp {
                     ui.vertical(|ui| {
                         self.registers(ui);
                         self.devices(ui);
+                        self.console(ui);
                     });
                 });
                 ui.push_id("Memory", |ui| {
@@ -610,16 +799,28 @@ impl eframe::App for CerealApp {
                         self.memory(ui);
                     });
                 });
+                ui.push_id("Source", |ui| {
+                    ui.vertical(|ui| {
+                        self.source(ui);
+                    });
+                });
                 ui.push_id("Breakpoints and Dumps", |ui| {
                     ui.vertical(|ui| {
                         self.show_breakpoints(ui);
+                        self.variables(ui);
+                        self.call_stack(ui);
                     });
                 });
             });
         });
 
         if self.execution_state == ExecutionState::Running {
-            self.run_frame().expect("No Execution Errors");
+            if let Err(e) = self.run_frame() {
+                self.execution_state = ExecutionState::Suspended;
+                self.next_stop = None;
+                self.source_step = None;
+                self.command_output.push_str(&format!("Error: {}\n", e));
+            }
         }
         if self.execution_state == ExecutionState::Suspended {
             let cmds = self.script_commands.clone();
@@ -632,9 +833,10 @@ impl eframe::App for CerealApp {
     }
 }
 
-// @Todo keep the machine around after an error
 pub fn run(cli_options: Options) -> i16 {
     let mut machine = Machine::new();
+    machine.devices = Devices::new(cli_options.timer_mode);
+    machine.pipeline = cli_options.pipeline;
 
     if let Some(dir) = cli_options.from_directory {
         std::env::set_current_dir(dir).expect("Cannot local directory\n");
@@ -672,7 +874,8 @@ pub fn run(cli_options: Options) -> i16 {
         0
     } else {
         let mut steps = 0;
-        while machine.pc() != 0x80ff {
+        let mut frames = 0;
+        loop {
             steps += 1;
             match cli_options.step_cap {
                 Some(cap) if steps > cap => panic!("exceeded step limit"),
@@ -680,18 +883,44 @@ pub fn run(cli_options: Options) -> i16 {
             }
 
             let mut trace = cli_options.trace_path.as_ref().map(|_| Trace::new());
-            match machine.step(&mut trace) {
-                Ok(()) => {}
+            let outcome = match machine.step(&mut trace) {
+                Ok(outcome) => outcome,
                 Err(e) => {
-                    eprintln!("Error: {:?}", e);
+                    eprintln!("Error: {}", e);
                     break;
                 }
-            }
+            };
             if let Some(trace) = trace {
                 trace
                     .write_to_file(trace_file.as_mut().unwrap())
                     .expect("Failed to write to a file");
             }
+
+            let output = machine.devices.take_output();
+            if !output.is_empty() {
+                let _ = stdout.write_all(&output);
+                let _ = stdout.flush();
+            }
+
+            if let (Some(path), CaptureMode::EachFrame) = (&cli_options.capture_path, cli_options.capture_mode) {
+                if machine.devices.frames != frames {
+                    frames = machine.devices.frames;
+                    let path = capture::numbered_path(path, frames);
+                    if let Err(e) = capture::write_frame(&path, machine.frame()) {
+                        eprintln!("Failed to capture frame to {:?}: {}", path, e);
+                    }
+                }
+            }
+
+            if outcome == StepOutcome::Halted {
+                break;
+            }
+        }
+
+        if let (Some(path), CaptureMode::OnHalt) = (&cli_options.capture_path, cli_options.capture_mode) {
+            if let Err(e) = capture::write_frame(path, machine.frame()) {
+                eprintln!("Failed to capture frame to {:?}: {}", path, e);
+            }
         }
 
         machine.registers[0]
@@ -0,0 +1,70 @@
+use super::decode::decode;
+use super::machine::{Machine, SourceLocation};
+use super::InstructionType;
+
+/// Whether the instruction at `address` transfers control to a subroutine or trap
+pub(super) fn is_call(machine: &Machine, address: u16) -> bool {
+    decode(machine.memory[address as usize], &mut None)
+        .is_ok_and(|i| matches!(i.ty, InstructionType::Jsr | InstructionType::Jsrr | InstructionType::Trap))
+}
+
+/// Where a call being stepped over comes back to: the instruction after it, with the stack no
+/// deeper than it was when the call was made
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) struct ReturnPoint {
+    address: u16,
+    stack_pointer: u16,
+}
+
+impl ReturnPoint {
+    /// The return point of the call at the PC
+    pub(super) fn new(machine: &Machine) -> Self {
+        ReturnPoint {
+            address: machine.pc.wrapping_add(1),
+            stack_pointer: machine.registers[6] as u16,
+        }
+    }
+
+    pub(super) fn reached(&self, machine: &Machine) -> bool {
+        // A recursive call can come back to the same address with a deeper stack
+        machine.pc == self.address && (machine.registers[6] as u16) >= self.stack_pointer
+    }
+}
+
+/// Runs until the line table maps the PC to a different source line than the one it started on
+pub(super) struct SourceStep {
+    from: Option<SourceLocation>,
+    over_calls: bool,
+    /// The call being stepped over
+    return_to: Option<ReturnPoint>,
+}
+
+impl SourceStep {
+    pub(super) fn new(machine: &Machine, over_calls: bool) -> Self {
+        SourceStep {
+            from: machine.source_location(machine.pc),
+            over_calls,
+            return_to: None,
+        }
+    }
+
+    pub(super) fn before_step(&mut self, machine: &Machine) {
+        if self.over_calls && self.return_to.is_none() && is_call(machine, machine.pc) {
+            self.return_to = Some(ReturnPoint::new(machine));
+        }
+    }
+
+    /// Returns whether the step is finished
+    pub(super) fn after_step(&mut self, machine: &Machine) -> bool {
+        if let Some(return_to) = self.return_to {
+            if !return_to.reached(machine) {
+                return false;
+            }
+            self.return_to = None;
+        }
+
+        machine
+            .source_location(machine.pc)
+            .is_some_and(|location| Some(location) != self.from)
+    }
+}
@@ -51,3 +51,119 @@ fn _simple_compiler_test(input: &[&str], output: &str) -> i16 {
 
     run(options)
 }
+
+#[test]
+fn debug_info_lines() {
+    let records = compile_with_debug_info("nice");
+    assert_eq!(records.files, ["data/c/simple_libc.asm", "data/c/nice.c", "data/c/simple_os.asm"]);
+
+    // LC and LEA each expand to CONST and HICONST, which keep the line they came from
+    let start = records.lines.iter().filter(|&&(address, _, _)| address < 7).copied().collect::<Vec<_>>();
+    assert_eq!(start, [(0, 15, 0), (1, 15, 0), (2, 16, 0), (3, 16, 0), (4, 17, 0), (5, 18, 0), (6, 19, 0)]);
+    assert!(records.lines.iter().any(|&(_, line, file)| file == 1 && line == 2), "return 69 has a line");
+}
+
+#[test]
+fn debug_info_variables() {
+    let records = compile_with_debug_info("procedure_call_with_args");
+    let variable = |name: &str| {
+        let variable = records.variables.iter().find(|v| v.name == name);
+        variable.unwrap_or_else(|| panic!("No variable record for '{name}'")).clone()
+    };
+    let proc = records.symbols["proc"];
+    let main = records.symbols["main"];
+
+    // Arguments are above the frame pointer from R5+3, locals below it
+    assert_eq!(variable("z"), Variable { kind: 1, procedure: proc, value: 3, ty: 0, name: "z".into() });
+    assert_eq!(variable("w"), Variable { kind: 1, procedure: proc, value: 4, ty: 0, name: "w".into() });
+    assert_eq!(variable("y"), Variable { kind: 1, procedure: main, value: -1i16 as u16, ty: 0, name: "y".into() });
+    assert_eq!(variable("x"), Variable { kind: 0, procedure: 0, value: records.symbols["x"], ty: 0, name: "x".into() });
+    assert_eq!(records.variables.len(), 4);
+}
+
+fn compile_with_debug_info(name: &str) -> DebugRecords {
+    let inputs = vec![
+        "data/c/simple_libc.asm".into(),
+        format!("data/c/{name}.c").into(),
+        "data/c/simple_os.asm".into(),
+    ];
+    let output = std::env::temp_dir().join(format!("cereal_debug_info_{name}.obj"));
+    let options = cereal::Options {
+        output_path: output.clone(),
+        debug_info: true,
+        input_paths: inputs,
+    };
+    cereal::compile(options).expect("Compilation success");
+
+    let bytes = std::fs::read(&output).expect("Object file was written");
+    debug_records(&bytes)
+}
+
+/// A VARIABLE record: kind (0 global, 1 frame), procedure, address or frame offset, type, name
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Variable {
+    kind: u16,
+    procedure: u16,
+    value: u16,
+    ty: u16,
+    name: String,
+}
+
+struct DebugRecords {
+    symbols: std::collections::HashMap<String, u16>,
+    files: Vec<String>,
+    /// (address, line, file index)
+    lines: Vec<(u16, u16, u16)>,
+    variables: Vec<Variable>,
+}
+
+fn debug_records(bytes: &[u8]) -> DebugRecords {
+    fn word(bytes: &[u8], cursor: &mut usize) -> u16 {
+        let word = u16::from_be_bytes([bytes[*cursor], bytes[*cursor + 1]]);
+        *cursor += 2;
+        word
+    }
+    fn name(bytes: &[u8], cursor: &mut usize) -> String {
+        let len = word(bytes, cursor) as usize;
+        let name = String::from_utf8_lossy(&bytes[*cursor..*cursor + len]).into_owned();
+        *cursor += len;
+        name
+    }
+
+    let mut records = DebugRecords {
+        symbols: Default::default(),
+        files: Vec::new(),
+        lines: Vec::new(),
+        variables: Vec::new(),
+    };
+    let mut cursor = 0;
+    while cursor < bytes.len() {
+        match word(bytes, &mut cursor) {
+            0xCADE | 0xDADA => {
+                let _address = word(bytes, &mut cursor);
+                cursor += 2 * word(bytes, &mut cursor) as usize;
+            }
+            0xC3B7 => {
+                let address = word(bytes, &mut cursor);
+                records.symbols.insert(name(bytes, &mut cursor), address);
+            }
+            0xF17E => records.files.push(name(bytes, &mut cursor)),
+            0x715E => {
+                let address = word(bytes, &mut cursor);
+                let line = word(bytes, &mut cursor);
+                let file = word(bytes, &mut cursor);
+                records.lines.push((address, line, file));
+            }
+            0x7A7E => {
+                let kind = word(bytes, &mut cursor);
+                let procedure = word(bytes, &mut cursor);
+                let value = word(bytes, &mut cursor);
+                let ty = word(bytes, &mut cursor);
+                let name = name(bytes, &mut cursor);
+                records.variables.push(Variable { kind, procedure, value, ty, name });
+            }
+            header => panic!("Unknown record header x{header:04X}"),
+        }
+    }
+    records
+}
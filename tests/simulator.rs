@@ -26,6 +26,253 @@ fn wireframe() {
     test_asm_from_script("wireframe");
 }
 
+#[test]
+fn headless_halt() {
+    // The embedded OS halts by clearing the MCR run bit, the step cap panics if it never does
+    let capture = std::env::temp_dir().join("cereal_headless_halt.png");
+    let _ = std::fs::remove_file(&capture);
+    let options = Options {
+        input_paths: vec!["data/asm/public-test_checkers_img.obj".into()],
+        step_cap: Some(1_000_000),
+        headless: true,
+        capture_path: Some(capture.clone()),
+        ..Default::default()
+    };
+    run(options);
+    assert!(capture.exists(), "The display is captured on halt");
+}
+
+#[test]
+fn next() {
+    // Steps over a trap and a call, comes back out of a recursive call at the caller's depth and
+    // stops at a breakpoint inside the callee
+    let expected = "\
+Assembly completed without errors or warnings
+Loading object file data/tests/asm/next.obj: code and data ...  symbols ...  file and line numbers ... 
+Breakpoint set at x0003
+use the 'stop' command to interrupt execution
+Hit breakpoint at x0003
+Check passed: PC is x0004
+Check passed: R1 is x0001
+Check passed: PC is x0006
+Check passed: R0 is x0000
+System reset
+Loading object file data/tests/asm/next.obj: code and data ...  symbols ...  file and line numbers ... 
+Breakpoint set at x0014
+use the 'stop' command to interrupt execution
+Hit breakpoint at x0014
+Breakpoint cleared at x0014
+Check passed: R6 is x7FFF
+Check passed: PC is x0015
+Check passed: R6 is x7FFF
+Check passed: R0 is x0000
+System reset
+Loading object file data/tests/asm/next.obj: code and data ...  symbols ...  file and line numbers ... 
+Breakpoint set at x0005
+Breakpoint set at x0017
+use the 'stop' command to interrupt execution
+Hit breakpoint at x0005
+Hit breakpoint at x0017
+Check passed: PC is x0017
+Check passed: R6 is x7FFE
+10 checks passed, 0 checks failed
+";
+    compare_by_lines(&script_output("next"), expected);
+}
+
+#[test]
+fn watch() {
+    let expected = "\
+Assembly completed without errors or warnings
+Loading object file data/tests/asm/watch.obj: code and data ...  symbols ...  file and line numbers ... 
+Watchpoint set on write x7FFE-x7FFF (x7FFE)
+use the 'stop' command to interrupt execution
+Watchpoint: Write of x7FFF by instruction at x0013: x0000 -> x0006
+use the 'stop' command to interrupt execution
+Watchpoint: Write of x7FFE by instruction at x0013: x0000 -> x0015
+Watchpoint cleared at x7FFE
+Watchpoint set on read x7FFF (x7FFF)
+use the 'stop' command to interrupt execution
+Watchpoint: Read of x7FFF by instruction at x0015: x0006 -> x0006
+Error: No watchpoint at x7FFE
+";
+    compare_by_lines(&script_output("watch"), expected);
+}
+
+#[test]
+fn check() {
+    let expected = "\
+R1 set to x0005
+Memory x4000 set to x1234
+Memory x4001 set to x1234
+Memory x4002 set to x0000
+Check passed: R1 is x0005
+Check failed: R1 is x0005, expected x0006
+Check passed: x4000 is x1234
+Check passed: x4001 is x1234
+Check passed: x4000 is x1234
+Check passed: x4001 is x1234
+Check failed: x4002 is x0000, expected x1234
+5 checks passed, 2 checks failed
+Check counts reset
+0 checks passed, 0 checks failed
+Cumulative: 5 checks passed, 2 checks failed
+";
+    compare_by_lines(&script_output("check"), expected);
+}
+
+#[test]
+fn loadhex_round_trip() {
+    let _ = std::fs::remove_file("data/tests/asm/loadhex.hex");
+    let expected = "\
+Memory x4000 set to x1234
+Memory x4001 set to xBEEF
+Memory x4002 set to x0000
+Memory x4003 set to xFFFF
+Memory dumped to data/tests/asm/loadhex.hex
+System reset
+Loaded 4 words from hex file data/tests/asm/loadhex.hex
+Check passed: x4000 is x1234
+Check passed: x4001 is xBEEF
+Check passed: x4002 is x0000
+Check passed: x4003 is xFFFF
+";
+    compare_by_lines(&script_output("loadhex"), expected);
+}
+
+#[test]
+fn loadhex_malformed() {
+    // Nothing is written when any line is bad
+    let expected = "\
+Error loading file 'tests/scripts/malformed.hex': invalid hex word 'xyzw' on line 5
+Check passed: x4000 is x0000
+";
+    compare_by_lines(&script_output("loadhex_malformed"), expected);
+}
+
+#[test]
+fn source_step() {
+    compile_with_debug_info("procedure_call_with_args", "source_step");
+    let expected = "\
+Loading object file data/tests/c/source_step.obj: code and data ...  symbols ...  file and line numbers ... 
+Breakpoint set at x0020
+use the 'stop' command to interrupt execution
+Hit breakpoint at x0020
+#0 x0025 main+5 at data/c/procedure_call_with_args.c:10
+    local    R5-1 x7FFB = x0000 (0) int y
+#0 x002E main+14 at data/c/procedure_call_with_args.c:11
+    local    R5-1 x7FFB = x0003 (3) int y
+System reset
+Loading object file data/tests/c/source_step.obj: code and data ...  symbols ...  file and line numbers ... 
+Breakpoint set at x0020
+use the 'stop' command to interrupt execution
+Hit breakpoint at x0020
+#0 x0010 proc at data/c/procedure_call_with_args.c:3
+    (frame not set up, locals not allocated)
+    argument R5+3 x7FF9 = x0005 (5) int z
+    argument R5+4 x7FFA = x0002 (2) int w
+#1 x002B main+11 at data/c/procedure_call_with_args.c:10
+    local    R5-1 x7FFB = x0000 (0) int y
+#0 x0014 proc+4 at data/c/procedure_call_with_args.c:4
+    argument R5+3 x7FF9 = x0005 (5) int z
+    argument R5+4 x7FFA = x0002 (2) int w
+#1 x002B main+11 at data/c/procedure_call_with_args.c:10
+    local    R5-1 x7FFB = x0000 (0) int y
+#0 x0018 proc+8 at data/c/procedure_call_with_args.c:5
+    argument R5+3 x7FF9 = x0005 (5) int z
+    argument R5+4 x7FFA = x0002 (2) int w
+#1 x002B main+11 at data/c/procedure_call_with_args.c:10
+    local    R5-1 x7FFB = x0000 (0) int y
+";
+    compare_by_lines(&script_output("source_step"), expected);
+}
+
+#[test]
+fn backtrace() {
+    // Stops in the prologue, on the stack pointer move before R5 is set, in the body, on the
+    // return and back in the caller
+    compile_with_debug_info("procedure_call_with_args", "backtrace");
+    let expected = "\
+Loading object file data/tests/c/backtrace.obj: code and data ...  symbols ...  file and line numbers ... 
+Breakpoint set at x0010
+Breakpoint set at x001F
+use the 'stop' command to interrupt execution
+Hit breakpoint at x0010
+#0 x0010 proc at data/c/procedure_call_with_args.c:3
+    (frame not set up, locals not allocated)
+    argument R5+3 x7FF9 = x0005 (5) int z
+    argument R5+4 x7FFA = x0002 (2) int w
+#1 x002B main+11 at data/c/procedure_call_with_args.c:10
+    local    R5-1 x7FFB = x0000 (0) int y
+#0 x0013 proc+3 at data/c/procedure_call_with_args.c:3
+    (frame not set up, locals not allocated)
+    argument R5+3 x7FF9 = x0005 (5) int z
+    argument R5+4 x7FFA = x0002 (2) int w
+#1 x002B main+11 at data/c/procedure_call_with_args.c:10
+    local    R5-1 x7FFB = x0000 (0) int y
+#0 x0014 proc+4 at data/c/procedure_call_with_args.c:4
+    argument R5+3 x7FF9 = x0005 (5) int z
+    argument R5+4 x7FFA = x0002 (2) int w
+#1 x002B main+11 at data/c/procedure_call_with_args.c:10
+    local    R5-1 x7FFB = x0000 (0) int y
+use the 'stop' command to interrupt execution
+Hit breakpoint at x001F
+#0 x001F proc+15 at data/c/procedure_call_with_args.c:5
+    (frame not set up, locals not allocated)
+    argument R5+3 x7FF9 = x0005 (5) int z
+    argument R5+4 x7FFA = x0002 (2) int w
+#1 x002B main+11 at data/c/procedure_call_with_args.c:10
+    local    R5-1 x7FFB = x0000 (0) int y
+#0 x002B main+11 at data/c/procedure_call_with_args.c:10
+    local    R5-1 x7FFB = x0000 (0) int y
+";
+    compare_by_lines(&script_output("backtrace"), expected);
+}
+
+#[test]
+fn print_var() {
+    compile_with_debug_info("procedure_call_with_args", "print_var");
+    let expected = "\
+Loading object file data/tests/c/print_var.obj: code and data ...  symbols ...  file and line numbers ... 
+Breakpoint set at x0018
+Breakpoint set at x002E
+use the 'stop' command to interrupt execution
+Hit breakpoint at x0018
+int z x7FF9 = x0005 (5)
+int w x7FFA = x0002 (2)
+int x x2001 = x0002 (2)
+Error: No variable named 'y' in scope
+use the 'stop' command to interrupt execution
+Hit breakpoint at x002E
+int y x7FFB = x0003 (3)
+Error: No variable named 'z' in scope
+";
+    compare_by_lines(&script_output("print_var"), expected);
+}
+
+/// Compiles `data/c/{name}.c` with debug info to `data/tests/c/{output}.obj` for a script to load
+fn compile_with_debug_info(name: &str, output: &str) {
+    let options = cereal::Options {
+        output_path: format!("data/tests/c/{output}.obj").into(),
+        debug_info: true,
+        input_paths: vec![
+            "data/c/simple_libc.asm".into(),
+            format!("data/c/{name}.c").into(),
+            "data/c/simple_os.asm".into(),
+        ],
+    };
+    cereal::compile(options).expect("Compilation success");
+}
+
+/// What the script printed to the console before quitting
+fn script_output(name: &str) -> String {
+    let output = std::process::Command::new("cargo")
+        .args(&["r", "--", "-s", &format!("tests/scripts/{name}_script")])
+        .output()
+        .expect("Failed to start child process");
+    String::from_utf8(output.stdout).expect("Console output is UTF-8")
+}
+
 fn test_asm_from_script(name: &str) {
     let _ = std::fs::remove_file(&format!("data/tests/asm/{name}.txt"));
     std::process::Command::new("cargo")